@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+use super::{LyricsProviderTrait, ProviderCapabilities, SearchLyricsInfo};
+
+/// 外部命令歌词源配置
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CommandConfig {
+    // 通过 sh -c 执行的命令模板，支持 {title} 和 {artist} 占位符（以 "$1"、"$2" 传入），为空时不启用
+    pub command: String,
+    // 单位毫秒，超时后结束命令
+    pub timeout: u64,
+}
+
+impl Default for CommandConfig {
+    fn default() -> Self {
+        CommandConfig {
+            command: String::new(),
+            timeout: 5000,
+        }
+    }
+}
+
+/// 执行外部命令获取歌词，命令的标准输出作为 LRC 解析
+pub struct CommandProvider {
+    config: CommandConfig,
+}
+
+impl CommandProvider {
+    pub fn new(config: CommandConfig) -> Self {
+        CommandProvider { config }
+    }
+}
+
+#[async_trait]
+impl LyricsProviderTrait for CommandProvider {
+    fn get_source_name(&self) -> String {
+        String::from("command")
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        // 命令是否联网无从得知，交给用户自己决定，离线模式下也保留
+        ProviderCapabilities {
+            synced: true,
+            offline: true,
+            ..Default::default()
+        }
+    }
+    async fn get_best_match_lyric(&self, title: &str, artist: &str, _album: &str, _length: u64, _url: &str) -> Result<SearchLyricsInfo> {
+        // 占位符替换为位置参数，标题和歌手作为独立参数传给 sh，不会拼进命令文本
+        let command = self
+            .config
+            .command
+            .replace("{title}", "\"$1\"")
+            .replace("{artist}", "\"$2\"");
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .arg("sh")
+            .arg(title)
+            .arg(artist)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::time::timeout(Duration::from_millis(self.config.timeout), child)
+            .await
+            .map_err(|_| anyhow::anyhow!("Command timed out"))??;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("Command exited with {}", output.status));
+        }
+
+        let (lyrics, synced) =
+            SearchLyricsInfo::parse_lyric_or_plain(&String::from_utf8_lossy(&output.stdout))?;
+        Ok(SearchLyricsInfo {
+            source: self.get_source_name(),
+            lyrics,
+            translations: BTreeMap::new(),
+            synced,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(command: &str) -> CommandProvider {
+        CommandProvider::new(CommandConfig {
+            command: String::from(command),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_command_output_as_lyric() {
+        let provider = provider(r"printf '[00:01.00]%s - %s\n' {artist} {title}");
+        let result = provider
+            .get_best_match_lyric("It's $HOME", "BY2", "", 0, "")
+            .await
+            .unwrap();
+        assert_eq!(result.lyrics.get(&1000).unwrap(), "BY2 - It's $HOME");
+    }
+
+    #[tokio::test]
+    async fn test_command_placeholder_in_title() {
+        let provider = provider(r"printf '[00:01.00]%s - %s\n' {artist} {title}");
+        let result = provider
+            .get_best_match_lyric("{artist}", "$(echo INJECTED)", "", 0, "")
+            .await
+            .unwrap();
+        assert_eq!(result.lyrics.get(&1000).unwrap(), "$(echo INJECTED) - {artist}");
+    }
+
+    #[tokio::test]
+    async fn test_command_failure() {
+        assert!(provider("true").get_best_match_lyric("a", "b", "", 0, "").await.is_err());
+        assert!(provider("exit 1").get_best_match_lyric("a", "b", "", 0, "").await.is_err());
+
+        let provider = CommandProvider::new(CommandConfig {
+            command: String::from("sleep 5"),
+            timeout: 100,
+        });
+        assert!(provider.get_best_match_lyric("a", "b", "", 0, "").await.is_err());
+    }
+}
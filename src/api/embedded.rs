@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{LyricsProviderTrait, NotFound, ProviderCapabilities, SearchLyricsInfo};
+
+/// 内嵌歌词源配置
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct EmbeddedConfig {
+    pub enabled: bool,
+}
+
+impl Default for EmbeddedConfig {
+    fn default() -> Self {
+        EmbeddedConfig { enabled: true }
+    }
+}
+
+// file:// URL 转换为本地路径，其他协议（如流媒体的 https://）返回 None
+fn file_url_to_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    if !path.starts_with('/') {
+        return None;
+    }
+    // 百分号编码按字节解码，文件名不一定是 UTF-8
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| bytes.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    Some(PathBuf::from(OsString::from_vec(decoded)))
+}
+
+// 从切片开头取出 len 个字节，长度不够时返回 None
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if data.len() < len {
+        return None;
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Some(head)
+}
+
+fn take_u32_le(data: &mut &[u8]) -> Option<usize> {
+    Some(u32::from_le_bytes(take(data, 4)?.try_into().ok()?) as usize)
+}
+
+// 从 Vorbis comment 中取出 LYRICS 或 UNSYNCEDLYRICS
+fn parse_vorbis_lyrics(mut block: &[u8]) -> Option<String> {
+    let vendor_len = take_u32_le(&mut block)?;
+    take(&mut block, vendor_len)?;
+    let count = take_u32_le(&mut block)?;
+    for _ in 0..count {
+        let len = take_u32_le(&mut block)?;
+        let comment = String::from_utf8_lossy(take(&mut block, len)?);
+        if let Some((key, value)) = comment.split_once('=') {
+            if key.eq_ignore_ascii_case("LYRICS") || key.eq_ignore_ascii_case("UNSYNCEDLYRICS") {
+                return Some(value.to_string());
+            }
+        }
+    }
+    None
+}
+
+// 读取 FLAC 的 Vorbis comment 元数据块，不是 FLAC 文件时返回 None
+fn read_flac_lyrics(path: &Path) -> Result<Option<String>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    file.read_exact(&mut magic)?;
+    if &magic != b"fLaC" {
+        return Ok(None);
+    }
+    loop {
+        let mut header = [0; 4];
+        file.read_exact(&mut header)?;
+        let is_last = header[0] & 0x80 != 0;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        // 类型 4 是 VORBIS_COMMENT
+        if header[0] & 0x7f == 4 {
+            let mut block = vec![0; len as usize];
+            file.read_exact(&mut block)?;
+            return Ok(parse_vorbis_lyrics(&block));
+        }
+        if is_last {
+            return Ok(None);
+        }
+        file.seek_relative(len as i64)?;
+    }
+}
+
+// 读取 ID3 标签，优先使用带时间轴的 SYLT，其次是 USLT
+fn read_id3_lyrics(path: &Path) -> Result<Option<SearchLyricsInfo>> {
+    let tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(err) if matches!(err.kind, id3::ErrorKind::NoTag) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    // MPEG 帧为单位的时间戳需要知道帧率才能换算，只支持毫秒
+    let synced = tag.synchronised_lyrics().find(|lyrics| {
+        lyrics.timestamp_format == id3::frame::TimestampFormat::Ms && !lyrics.content.is_empty()
+    });
+    if let Some(synced) = synced {
+        return Ok(Some(SearchLyricsInfo {
+            source: String::from("embedded"),
+            lyrics: synced
+                .content
+                .iter()
+                .map(|(timestamp, text)| (*timestamp as u64, text.trim().to_string()))
+                .collect(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        }));
+    }
+    let unsynced = tag
+        .lyrics()
+        .find(|lyrics| !lyrics.text.trim().is_empty())
+        .map(|lyrics| text_to_search_lyrics_info(&lyrics.text));
+    unsynced.transpose()
+}
+
+// 很多软件把 LRC 直接写进纯文本歌词标签，能按 LRC 解析时使用时间轴，否则整段放在 0 毫秒处
+// 只有 [ti:] 等标签、没有歌词内容时返回 NotFound
+fn text_to_search_lyrics_info(text: &str) -> Result<SearchLyricsInfo> {
+    // 本地文件就是正在播放的歌曲，时间轴有问题时也按纯文本显示，不交给其他歌词源
+    let (lyrics, synced) = match SearchLyricsInfo::parse_lyric_or_plain(text) {
+        Ok(parsed) => parsed,
+        Err(err) if err.is::<NotFound>() => return Err(err),
+        Err(_) => ([(0, text.trim().to_string())].into(), false),
+    };
+    Ok(SearchLyricsInfo {
+        source: String::from("embedded"),
+        lyrics,
+        translations: BTreeMap::new(),
+        synced,
+        // 就是正在播放的文件，时长一定一致
+        delta_abs: 0,
+        title_similarity: 1.0,
+    })
+}
+
+/// 读取正在播放的本地文件中内嵌的歌词，完全离线且一定是同一首歌
+pub struct EmbeddedProvider;
+
+#[async_trait]
+impl LyricsProviderTrait for EmbeddedProvider {
+    fn get_source_name(&self) -> String {
+        String::from("embedded")
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            synced: true,
+            offline: true,
+            ..Default::default()
+        }
+    }
+    async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, url: &str) -> Result<SearchLyricsInfo> {
+        let path = file_url_to_path(url).ok_or_else(|| NotFound(String::from("Not a local file")))?;
+        let lyrics = match read_flac_lyrics(&path)? {
+            Some(text) => Some(text_to_search_lyrics_info(&text)?),
+            None => read_id3_lyrics(&path)?,
+        };
+        lyrics.ok_or_else(|| NotFound(String::from("No embedded lyrics")).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_url_to_path() {
+        assert_eq!(
+            file_url_to_path("file:///home/user/Music/%E7%8E%AB%E7%91%B0%20%E5%B0%91%E5%B9%B4.flac"),
+            Some(PathBuf::from("/home/user/Music/玫瑰 少年.flac"))
+        );
+        assert_eq!(file_url_to_path("file:///a/100%.mp3"), Some(PathBuf::from("/a/100%.mp3")));
+        assert_eq!(file_url_to_path("https://example.com/song.mp3"), None);
+        assert_eq!(file_url_to_path(""), None);
+    }
+
+    #[test]
+    fn test_read_flac_lyrics() {
+        let comment = b"LYRICS=[00:01.00]first line";
+        let mut vorbis = Vec::new();
+        vorbis.extend(3u32.to_le_bytes());
+        vorbis.extend(b"abc");
+        vorbis.extend(1u32.to_le_bytes());
+        vorbis.extend((comment.len() as u32).to_le_bytes());
+        vorbis.extend(comment);
+
+        let mut flac = b"fLaC".to_vec();
+        // STREAMINFO 块，内容不关心
+        flac.extend([0, 0, 0, 2, 0, 0]);
+        flac.extend([0x84, 0, 0, vorbis.len() as u8]);
+        flac.extend(&vorbis);
+        let path = std::env::temp_dir().join("mpris-lyrics-rs-embedded-test.flac");
+        std::fs::write(&path, flac).unwrap();
+
+        let text = read_flac_lyrics(&path).unwrap().unwrap();
+        let info = text_to_search_lyrics_info(&text).unwrap();
+        assert!(info.is_synced());
+        assert_eq!(info.lyrics.get(&1000).unwrap(), "first line");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_plain_text_lyrics() {
+        let info = text_to_search_lyrics_info("first line\nsecond line\n").unwrap();
+        assert!(!info.is_synced());
+        assert_eq!(info.lyrics.get(&0).unwrap(), "first line\nsecond line");
+    }
+
+    #[test]
+    fn test_tags_only_lyrics_not_found() {
+        let err = text_to_search_lyrics_info("[ti:玫瑰少年]\n[ar:蔡依林]\n").unwrap_err();
+        assert!(err.is::<NotFound>());
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+use async_trait::async_trait;
+use reqwest::header::USER_AGENT;
+use reqwest::StatusCode;
+use serde_json::Value;
+use anyhow::Result;
+
+use super::matching::{select_candidate, SongCandidate};
+use super::{http_client, send_with_retry, LyricsProviderTrait, NotFound, ProviderCapabilities, RetryPolicy, SearchLyricsInfo};
+
+const BASE_URL: &str = "https://lrclib.net/api";
+
+// LRCLIB 要求带上能识别客户端的 User-Agent
+fn user_agent() -> String {
+    format!(
+        "{} {} (https://github.com/OHMCFXG/mpris-lyrics-rs)",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION")
+    )
+}
+
+// 按标题、歌手、专辑和时长精确查找，找不到时返回 None
+async fn get(client: &reqwest::Client, retry: RetryPolicy, title: &str, artist: &str, album: &str, length: u64) -> Result<Option<Value>> {
+    let duration = (length / 1000).to_string();
+    let mut params = vec![
+        ("track_name", title),
+        ("artist_name", artist),
+        ("duration", duration.as_str()),
+    ];
+    if !album.is_empty() {
+        params.push(("album_name", album));
+    }
+    let request = client
+        .get(format!("{}/get", BASE_URL))
+        .query(&params)
+        .header(USER_AGENT, user_agent());
+    let resp = send_with_retry(request, retry).await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(resp.error_for_status()?.json().await?))
+}
+
+// 按 LRCLIB 的记录 id 获取
+async fn get_by_id(client: &reqwest::Client, retry: RetryPolicy, id: &str) -> Result<Value> {
+    let request = client
+        .get(format!("{}/get/{}", BASE_URL, id))
+        .header(USER_AGENT, user_agent());
+    let resp = send_with_retry(request, retry).await?;
+    Ok(resp.error_for_status()?.json().await?)
+}
+
+async fn search(client: &reqwest::Client, retry: RetryPolicy, title: &str, artist: &str) -> Result<Value> {
+    let request = client
+        .get(format!("{}/search", BASE_URL))
+        .query(&[("track_name", title), ("artist_name", artist)])
+        .header(USER_AGENT, user_agent());
+    let resp = send_with_retry(request, retry).await?;
+    Ok(resp.error_for_status()?.json().await?)
+}
+
+// 歌词字段可能是 null 或空字符串
+fn lyric_field<'a>(record: &'a Value, field: &str) -> Option<&'a str> {
+    record[field].as_str().filter(|text| !text.trim().is_empty())
+}
+
+// 从搜索结果中选出最匹配且有歌词的条目，返回条目下标和时长差
+fn find_best_match(
+    records: &[Value],
+    title: &str,
+    length: u64,
+    min_title_similarity: f64,
+) -> Result<(usize, i64, f64)> {
+    let candidates = records
+        .iter()
+        .enumerate()
+        .filter(|(_, record)| {
+            lyric_field(record, "syncedLyrics").is_some() || lyric_field(record, "plainLyrics").is_some()
+        })
+        .map(|(index, record)| SongCandidate {
+            id: index,
+            title: record["trackName"].as_str().unwrap_or_default().to_string(),
+            length: record["duration"].as_f64().map(|duration| (duration * 1000.0) as u64),
+        })
+        .collect();
+    select_candidate(candidates, title, length, min_title_similarity)
+}
+
+// 优先使用带时间轴的歌词，只有纯文本歌词时整段放在 0 毫秒处
+fn to_search_lyrics_info(record: &Value, delta_abs: i64) -> Result<SearchLyricsInfo> {
+    if let Some(synced) = lyric_field(record, "syncedLyrics") {
+        return Ok(SearchLyricsInfo {
+            source: String::from("lrclib"),
+            lyrics: SearchLyricsInfo::parse_lyric(synced)?,
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs,
+            title_similarity: 1.0,
+        });
+    }
+    let plain = lyric_field(record, "plainLyrics").ok_or_else(|| NotFound(String::from("No lyric found")))?;
+    Ok(SearchLyricsInfo {
+        source: String::from("lrclib"),
+        lyrics: [(0, plain.trim().to_string())].into(),
+        translations: BTreeMap::new(),
+        synced: false,
+        delta_abs,
+        title_similarity: 1.0,
+    })
+}
+
+pub struct LrclibLyricsProvider {
+    client: reqwest::Client,
+    min_title_similarity: f64,
+    retry: RetryPolicy,
+}
+
+impl LrclibLyricsProvider {
+    pub fn new(min_title_similarity: f64, timeout: Duration, retry: RetryPolicy) -> Self {
+        LrclibLyricsProvider {
+            client: http_client(timeout),
+            min_title_similarity,
+            retry,
+        }
+    }
+}
+
+#[async_trait]
+impl LyricsProviderTrait for LrclibLyricsProvider {
+    // 获取歌词源名称
+    fn get_source_name(&self) -> String {
+        String::from("lrclib")
+    }
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            synced: true,
+            id_lookup: true,
+            ..Default::default()
+        }
+    }
+    async fn get_best_match_lyric(&self, title: &str, artist: &str, album: &str, length: u64, _url: &str) -> Result<SearchLyricsInfo> {
+        // 精确查找按秒匹配时长，查到的就是同一首，时长差按秒内的误差计算
+        if let Some(record) = get(&self.client, self.retry, title, artist, album, length).await? {
+            if lyric_field(&record, "syncedLyrics").is_some() || lyric_field(&record, "plainLyrics").is_some() {
+                let duration = record["duration"].as_f64().unwrap_or_default();
+                let delta_abs = ((duration * 1000.0) as i64 - length as i64).abs();
+                return to_search_lyrics_info(&record, delta_abs);
+            }
+        }
+
+        let data = search(&self.client, self.retry, title, artist).await?;
+        let records = data.as_array().ok_or(anyhow::anyhow!("Not an array"))?;
+        let (index, delta_abs, title_similarity) = find_best_match(records, title, length, self.min_title_similarity)?;
+        let mut lyrics = to_search_lyrics_info(&records[index], delta_abs)?;
+        lyrics.title_similarity = title_similarity;
+        Ok(lyrics)
+    }
+    async fn get_lyric_by_id(&self, id: &str) -> Result<SearchLyricsInfo> {
+        // id 会拼进 URL 路径，只接受数字
+        if id.is_empty() || !id.chars().all(|c| c.is_ascii_digit()) {
+            return Err(anyhow::anyhow!("Invalid lrclib id: {}", id));
+        }
+        to_search_lyrics_info(&get_by_id(&self.client, self.retry, id).await?, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_to_search_lyrics_info() {
+        let record = json!({ "syncedLyrics": "[00:01.00]第一句", "plainLyrics": "第一句" });
+        let info = to_search_lyrics_info(&record, 0).unwrap();
+        assert!(info.is_synced());
+        assert_eq!(info.lyrics.get(&1000).unwrap(), "第一句");
+
+        // 只有纯文本歌词
+        let record = json!({ "syncedLyrics": null, "plainLyrics": "第一句\n第二句\n" });
+        let info = to_search_lyrics_info(&record, 0).unwrap();
+        assert!(!info.is_synced());
+        assert_eq!(info.lyrics.get(&0).unwrap(), "第一句\n第二句");
+
+        let record = json!({ "syncedLyrics": "", "plainLyrics": null });
+        assert!(to_search_lyrics_info(&record, 0).is_err());
+    }
+
+    #[test]
+    fn test_find_best_match_skips_records_without_lyrics() {
+        let records = json!([
+            { "trackName": "玫瑰少年", "duration": 216.0, "syncedLyrics": null, "plainLyrics": null },
+            { "trackName": "玫瑰少年", "duration": 215.0, "syncedLyrics": "[00:01.00]第一句" }
+        ]);
+        assert_eq!(
+            find_best_match(records.as_array().unwrap(), "玫瑰少年", 216000, 0.3).unwrap(),
+            (1, 1000, 1.0)
+        );
+    }
+}
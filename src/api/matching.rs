@@ -0,0 +1,139 @@
+// 没有启用任何网络歌词源时，匹配函数只在测试中使用
+#![cfg_attr(not(any(feature = "netease", feature = "qqmusic")), allow(dead_code))]
+
+use anyhow::Result;
+
+use super::NotFound;
+use crate::utils::string::normalize_search_title;
+
+/// 搜索结果中的一首候选歌曲
+pub struct SongCandidate<T> {
+    pub id: T,
+    pub title: String,
+    // 单位毫秒，搜索结果里没有时长时为 None
+    pub length: Option<u64>,
+}
+
+/// 从候选歌曲中选出最匹配的一首，返回其 id、时长差和标题相似度
+///
+/// 标题相似度低于 min_title_similarity 的候选直接排除，宁可没有歌词也不显示别的歌的歌词，
+/// 剩下的候选优先选时长一致的，否则取第一首
+///
+/// 相似度取原标题和去掉 feat./Remaster 等附加信息后的标题中较高的一个，
+/// 否则 "Stay [ft. Justin Bieber]" 和搜索结果 "Stay" 的相似度过低
+pub fn select_candidate<T>(
+    candidates: Vec<SongCandidate<T>>,
+    title: &str,
+    length: u64,
+    min_title_similarity: f64,
+) -> Result<(T, i64, f64)> {
+    let search_title = normalize_search_title(title);
+    let scored = candidates
+        .into_iter()
+        .map(|candidate| {
+            let similarity = title_similarity(title, &candidate.title)
+                .max(title_similarity(&search_title, &candidate.title));
+            (similarity, candidate)
+        })
+        .collect::<Vec<_>>();
+    let best = scored
+        .iter()
+        .max_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(similarity, candidate)| (*similarity, candidate.title.clone()))
+        .ok_or_else(|| NotFound(String::from("No songs found")))?;
+
+    let mut accepted = scored
+        .into_iter()
+        .filter(|(similarity, _)| *similarity >= min_title_similarity)
+        .collect::<Vec<_>>();
+    if accepted.is_empty() {
+        return Err(NotFound(format!(
+            "Best title similarity {:.2} ({}) is below {:.2}",
+            best.0, best.1, min_title_similarity
+        ))
+        .into());
+    }
+
+    let index = accepted
+        .iter()
+        .position(|(_, candidate)| candidate.length == Some(length))
+        .unwrap_or(0);
+    let (similarity, candidate) = accepted.swap_remove(index);
+    let delta_abs = (candidate.length.unwrap_or(0) as i64 - length as i64).abs();
+    Ok((candidate.id, delta_abs, similarity))
+}
+
+/// 标题相似度，0~1，忽略大小写、空白和标点
+///
+/// 曲目标题为空时无法判断，视为完全匹配
+pub fn title_similarity(title: &str, other: &str) -> f64 {
+    let normalize = |text: &str| {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect::<Vec<_>>()
+    };
+    let (title, other) = (normalize(title), normalize(other));
+    if title.is_empty() {
+        return 1.0;
+    }
+    let max_len = title.len().max(other.len());
+    1.0 - levenshtein(&title, &other) as f64 / max_len as f64
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let cost = if a_char == b_char { 0 } else { 1 };
+            current[j + 1] = (previous[j] + cost)
+                .min(previous[j + 1] + 1)
+                .min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_similarity() {
+        assert_eq!(title_similarity("玫瑰少年", "玫瑰少年"), 1.0);
+        assert_eq!(title_similarity("Hello, World", "hello world"), 1.0);
+        assert!(title_similarity("玫瑰少年", "玫瑰少年 (Live)") > 0.3);
+        assert!(title_similarity("玫瑰少年", "爱的魔法") < 0.3);
+    }
+
+    #[test]
+    fn test_select_candidate_rejects_low_similarity() {
+        let candidates = vec![
+            SongCandidate {
+                id: 1,
+                title: String::from("爱的魔法"),
+                length: Some(216000),
+            },
+            SongCandidate {
+                id: 2,
+                title: String::from("玫瑰少年"),
+                length: Some(215000),
+            },
+        ];
+        // 时长一致但标题完全不同的候选被排除
+        assert_eq!(
+            select_candidate(candidates, "玫瑰少年", 216000, 0.3).unwrap(),
+            (2, 1000, 1.0)
+        );
+
+        let candidates = vec![SongCandidate {
+            id: 1,
+            title: String::from("爱的魔法"),
+            length: Some(216000),
+        }];
+        assert!(select_candidate(candidates, "玫瑰少年", 216000, 0.3).is_err());
+    }
+}
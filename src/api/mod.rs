@@ -3,25 +3,136 @@ use std::collections::BTreeMap;
 use anyhow::Result;
 use async_trait::async_trait;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 
+pub mod command;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod matching;
+#[cfg(feature = "netease")]
 pub mod netease;
+#[cfg(feature = "lrclib")]
+pub mod lrclib;
+#[cfg(feature = "qqmusic")]
 pub mod qq;
 
+// 网络歌词源单次请求的默认超时，单位秒
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
 pub const REQWEST_TIMEOUT: u64 = 3;
 
+// 网络歌词源共用的 HTTP 客户端，超时对每次请求生效
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+fn http_client(timeout: std::time::Duration) -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()
+        .expect("创建 HTTP 客户端失败")
+}
+
+// 网络歌词源请求失败时默认的重试次数
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+pub const DEFAULT_RETRIES: u32 = 2;
+
+// 第 attempt 次重试前的等待时间，从 200 毫秒开始翻倍，加上最多 100 毫秒的随机抖动
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+fn retry_delay(attempt: u32) -> std::time::Duration {
+    let jitter = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.subsec_nanos() as u64 % 100)
+        .unwrap_or(0);
+    std::time::Duration::from_millis((200 << attempt.min(5)) + jitter)
+}
+
+/// 网络歌词源请求失败时的重试设置
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RetryPolicy {
+    // 连接失败、超时和 5xx 时的重试次数
+    pub retries: u32,
+    // 安静模式下不打印重试提示
+    pub quiet: bool,
+}
+
+// 发送请求，连接失败、超时和 5xx 时按指数退避重试，4xx 和其他错误直接返回
+#[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+async fn send_with_retry(request: reqwest::RequestBuilder, retry: RetryPolicy) -> Result<reqwest::Response> {
+    let mut attempt = 0;
+    loop {
+        let result = request
+            .try_clone()
+            .ok_or(anyhow::anyhow!("Request cannot be retried"))?
+            .send()
+            .await;
+        let reason = match &result {
+            Ok(resp) if resp.status().is_server_error() => resp.status().to_string(),
+            Err(err) if err.is_connect() || err.is_timeout() => err.to_string(),
+            _ => return Ok(result?),
+        };
+        if attempt >= retry.retries {
+            return Ok(result?);
+        }
+        attempt += 1;
+        let delay = retry_delay(attempt - 1);
+        if !retry.quiet {
+            eprintln!("请求失败，{} 毫秒后第 {} 次重试: {}", delay.as_millis(), attempt, reason);
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// 歌词源明确回答没有这首歌或没有歌词
+///
+/// 和网络错误、超时等临时失败区分开，所有歌词源都是这种结果时才缓存“没有歌词”
+#[derive(Debug)]
+pub struct NotFound(pub String);
+
+impl std::fmt::Display for NotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for NotFound {}
+
 #[derive(Debug)]
 pub struct SearchLyricsInfo {
     pub source: String,
     pub lyrics: BTreeMap<u64, String>,
+    // 翻译歌词，和原歌词时间相同的行才会显示
+    pub translations: BTreeMap<u64, String>,
+    // 歌词源明确只有纯文本歌词时为 false，此时全部歌词放在 0 毫秒处
+    pub synced: bool,
     pub delta_abs: i64,
+    // 匹配到的歌曲标题和当前标题的相似度，0~1，按 id 或本地文件获取的歌词为 1
+    pub title_similarity: f64,
 }
 
 impl SearchLyricsInfo {
-    fn parse_lyric(lyric: &str) -> BTreeMap<u64, String> {
+    // 是否为带时间轴的歌词，不带时间轴的行解析时会被跳过
+    pub fn is_synced(&self) -> bool {
+        self.synced && !self.lyrics.is_empty()
+    }
+
+    // 超过 30% 的行（不算第一行）时间都是 0 时，认为是解析出错或歌词源返回的数据有问题，
+    // 返回错误让其他歌词源兜底，否则同一时间的行会互相覆盖，只剩一行歌词
+    pub(crate) fn parse_lyric(lyric: &str) -> Result<BTreeMap<u64, String>> {
         let mut result = BTreeMap::new();
+        let mut line_count = 0;
+        let mut zero_count = 0;
         let regex = Regex::new(r"^\d+:\d+\.\d+$").unwrap();
+        // 逐字歌词的时间标签，如 [00:12.00]<00:12.00>La <00:12.50>la，暂不支持逐字显示，直接去掉
+        let word_regex = Regex::new(r"<\d+:\d+\.\d+>").unwrap();
+        let mut offset: i64 = 0;
         for line in lyric.lines() {
             let line = line.trim();
+            // [offset:500] 整体调整时间，单位毫秒，正数表示歌词提前显示
+            if let Some(value) = line
+                .strip_prefix("[offset:")
+                .and_then(|rest| rest.strip_suffix("]"))
+            {
+                offset = value.trim().parse().unwrap_or(0);
+                continue;
+            }
             // 跳过元数据行和空行
             if line.is_empty()
                 || !line.starts_with("[")
@@ -29,40 +140,460 @@ impl SearchLyricsInfo {
             {
                 continue;
             }
-            let mut parts = line.splitn(2, "]");
-            let time_text = parts.next().unwrap().replace("[", "");
-            // 校验时间格式，网易有时会返回奇奇怪怪的格式
-            if !regex.is_match(&time_text) {
+            // 副歌等重复的歌词可能在一行里带多个时间标签，如 [00:12.00][01:30.00]歌词
+            let mut timestamps = Vec::new();
+            let mut text = line;
+            while let Some((time_text, rest)) = text
+                .strip_prefix("[")
+                .and_then(|text| text.split_once("]"))
+            {
+                // 校验时间格式，网易有时会返回奇奇怪怪的格式
+                if !regex.is_match(time_text) {
+                    break;
+                }
+                timestamps.push(Self::parse_timestamp(time_text));
+                text = rest;
+            }
+            if timestamps.is_empty() {
                 continue;
             }
-            let mut time_parts = time_text.split(":");
-            let minutes = time_parts.next().unwrap().parse::<u64>().unwrap();
-            let mut sec_parts = time_parts.next().unwrap().split(".");
-            let seconds = sec_parts.next().unwrap().parse::<u64>().unwrap();
-            let millis = sec_parts.next().unwrap().parse::<u64>().unwrap();
-            let timestamp = minutes * 60 * 1000 + seconds * 1000 + millis;
-            let lyric = parts
-                .next()
-                .unwrap()
+            line_count += 1;
+            if timestamps.contains(&0) {
+                zero_count += 1;
+            }
+            let lyric = word_regex
+                .replace_all(text, "")
                 .trim()
                 .replace("’", "'")
                 .replace("&apos;", "'");
-            result.insert(timestamp, lyric.to_string());
+            for timestamp in timestamps {
+                result.insert(timestamp, lyric.clone());
+            }
+        }
+        if zero_count > 1 && (zero_count - 1) * 10 > line_count * 3 {
+            return Err(anyhow::anyhow!(
+                "Malformed lyric: {} of {} lines at 00:00",
+                zero_count,
+                line_count
+            ));
+        }
+        if offset != 0 {
+            result = result
+                .into_iter()
+                .map(|(timestamp, lyric)| ((timestamp as i64 - offset).max(0) as u64, lyric))
+                .collect();
+        }
+        Ok(result)
+    }
+
+    // 没有任何时间标签的歌词按纯文本处理，整段放在 0 毫秒处，同时返回是否带时间轴
+    // [ti:] 之类的标签行不算歌词，去掉后什么都没有时返回错误，让其他歌词源兜底
+    pub(crate) fn parse_lyric_or_plain(lyric: &str) -> Result<(BTreeMap<u64, String>, bool)> {
+        let lyrics = Self::parse_lyric(lyric)?;
+        if !lyrics.is_empty() {
+            return Ok((lyrics, true));
+        }
+        let tag_regex = Regex::new(r"^\[[A-Za-z]+:.*\]$").unwrap();
+        let plain = lyric
+            .lines()
+            .map(str::trim)
+            .filter(|line| !tag_regex.is_match(line))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if plain.trim().is_empty() {
+            return Err(NotFound(String::from("No lyric found")).into());
+        }
+        Ok(([(0, plain.trim().to_string())].into(), false))
+    }
+
+    // 合并间隔小于 window 毫秒的相邻重复歌词，只保留第一行
+    pub fn dedup_adjacent_lines(&mut self, window: u64) {
+        let mut previous: Option<(u64, String)> = None;
+        self.lyrics.retain(|timestamp, lyric| {
+            let duplicate = previous
+                .as_ref()
+                .is_some_and(|(time, text)| text == lyric && timestamp - time < window);
+            previous = Some((*timestamp, lyric.clone()));
+            !duplicate
+        });
+    }
+
+    // 合并所有相邻的重复歌词，合并后第一行一直显示到下一句不同的歌词
+    pub fn collapse_repeated_lines(&mut self) {
+        self.dedup_adjacent_lines(u64::MAX);
+    }
+
+    // 纯音乐的歌词通常只有一两行 "此歌曲为没有填词的纯音乐，请您欣赏" 之类的提示
+    // 没有时间轴的歌词整段存为一项，按文本行数而不是项数判断
+    pub fn is_instrumental(&self, markers: &[String]) -> bool {
+        let line_count = self
+            .lyrics
+            .values()
+            .flat_map(|lyric| lyric.lines())
+            .filter(|line| !line.trim().is_empty())
+            .count();
+        line_count <= 3
+            && self.lyrics.values().any(|lyric| {
+                let lyric = lyric.to_lowercase();
+                markers
+                    .iter()
+                    .any(|marker| lyric.contains(&marker.to_lowercase()))
+            })
+    }
+
+    // 去掉开头的制作信息行（作词、作曲、"标题 - 歌手" 等），只处理开头，歌词全是制作信息时保留原样
+    pub fn skip_credit_lines(&mut self, title: &str, artist: &str) {
+        let credits = self
+            .lyrics
+            .iter()
+            .take_while(|(_, lyric)| is_credit_line(lyric, title, artist))
+            .map(|(timestamp, _)| *timestamp)
+            .collect::<Vec<_>>();
+        if credits.len() == self.lyrics.len() {
+            return;
         }
-        result
+        for timestamp in credits {
+            self.lyrics.remove(&timestamp);
+        }
+    }
+
+    // 解析 mm:ss.xx 格式的时间，小数部分可能是 1~3 位
+    fn parse_timestamp(time_text: &str) -> u64 {
+        let (minutes, seconds) = time_text.split_once(":").unwrap();
+        let (seconds, fraction) = seconds.split_once(".").unwrap();
+        let minutes = minutes.parse::<u64>().unwrap();
+        let seconds = seconds.parse::<u64>().unwrap();
+        let fraction = &fraction[..fraction.len().min(3)];
+        let millis = fraction.parse::<u64>().unwrap() * 10u64.pow(3 - fraction.len() as u32);
+        minutes * 60 * 1000 + seconds * 1000 + millis
+    }
+}
+
+// 带冒号的制作信息前缀，如 "作词 : 林夕"
+const CREDIT_PREFIXES: [&str; 16] = [
+    "词", "曲", "作词", "作曲", "编曲", "制作人", "监制", "混音", "母带", "和声", "吉他", "贝斯",
+    "鼓", "录音", "lyrics", "music",
+];
+// 英文制作信息，如 "Lyrics by xxx"
+const CREDIT_BY_PREFIXES: [&str; 5] = [
+    "lyrics by", "written by", "composed by", "arranged by", "produced by",
+];
+
+fn is_credit_line(lyric: &str, title: &str, artist: &str) -> bool {
+    let lyric = lyric.trim().to_lowercase();
+    if lyric == format!("{} - {}", title, artist).to_lowercase()
+        || lyric == format!("{} - {}", artist, title).to_lowercase()
+    {
+        return true;
+    }
+    let has_colon = |prefix: &str| {
+        lyric
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.trim_start().starts_with([':', '：']))
+    };
+    CREDIT_PREFIXES.iter().any(|prefix| has_colon(prefix))
+        || CREDIT_BY_PREFIXES.iter().any(|prefix| lyric.starts_with(prefix))
+}
+
+/// 单个歌词源的配置，未设置的项使用全局配置
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct SourceConfig {
+    // 覆盖全局的 matching.min_title_similarity
+    pub min_title_similarity: Option<f64>,
+    // 单次 HTTP 请求的超时，单位秒，默认 3 秒
+    pub timeout_seconds: Option<u64>,
+    // 连接失败、超时和 5xx 时的重试次数，默认 2 次
+    pub retries: Option<u32>,
+    // 设为 false 时不使用这个歌词源，默认启用
+    pub enabled: Option<bool>,
+}
+
+/// 歌词源支持的能力，用于按能力筛选歌词源，并在 --build-info 中展示
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderCapabilities {
+    // 提供带时间轴的歌词
+    pub synced: bool,
+    // 提供逐字时间轴
+    pub word_timing: bool,
+    // 提供翻译
+    pub translation: bool,
+    // 不需要联网
+    pub offline: bool,
+    // 支持按歌曲 id 直接获取歌词
+    pub id_lookup: bool,
+}
+
+impl ProviderCapabilities {
+    // 列出支持的能力名称
+    pub fn names(&self) -> Vec<&'static str> {
+        [
+            ("synced", self.synced),
+            ("word_timing", self.word_timing),
+            ("translation", self.translation),
+            ("offline", self.offline),
+            ("id_lookup", self.id_lookup),
+        ]
+        .iter()
+        .filter(|(_, supported)| *supported)
+        .map(|(name, _)| *name)
+        .collect()
     }
 }
 
 #[async_trait]
-pub trait LyricsProviderTrait {
+pub trait LyricsProviderTrait: Sync {
     // 获取歌词源名称
     fn get_source_name(&self) -> String;
-    async fn get_best_match_lyric(&self, keyword: &str, length: u64) -> Result<SearchLyricsInfo>;
+    // 获取歌词源支持的能力
+    fn capabilities(&self) -> ProviderCapabilities;
+    async fn get_best_match_lyric(
+        &self,
+        title: &str,
+        artist: &str,
+        album: &str,
+        length: u64,
+        // 播放器提供的 xesam:url，可能为空
+        url: &str,
+    ) -> Result<SearchLyricsInfo>;
+    // 按歌词源中的歌曲 id 直接获取歌词，capabilities 中 id_lookup 为 true 时可用
+    async fn get_lyric_by_id(&self, _id: &str) -> Result<SearchLyricsInfo> {
+        Err(anyhow::anyhow!("Lookup by id is not supported"))
+    }
 }
 
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lyric_multiple_timestamps() {
+        let lyric = "[00:01.00]第一句\n[00:05.50][00:20.50]副歌\n[00:10.00]第二句";
+        let result = SearchLyricsInfo::parse_lyric(lyric).unwrap();
+        let lines = result.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            vec![(1000, "第一句"), (5500, "副歌"), (10000, "第二句"), (20500, "副歌")]
+        );
+
+        // 随播放进度推进，重复的副歌应当在各自的时间段内被选中
+        let line_at = |position: u64| result.range(..=position).next_back().map(|(_, v)| v.as_str());
+        assert_eq!(line_at(6000), Some("副歌"));
+        assert_eq!(line_at(15000), Some("第二句"));
+        assert_eq!(line_at(21000), Some("副歌"));
+    }
+
+    #[test]
+    fn test_capability_names() {
+        let capabilities = ProviderCapabilities {
+            synced: true,
+            translation: true,
+            id_lookup: true,
+            ..Default::default()
+        };
+        assert_eq!(capabilities.names(), vec!["synced", "translation", "id_lookup"]);
+        assert!(ProviderCapabilities::default().names().is_empty());
+    }
+
+    #[test]
+    fn test_parse_lyric_word_timestamps() {
+        let lyric = "[00:12.00]<00:12.00>La <00:12.50>la<00:13.00>\n[00:14.00]普通的一行";
+        let result = SearchLyricsInfo::parse_lyric(lyric).unwrap();
+        assert_eq!(result.get(&12000).unwrap(), "La la");
+        assert_eq!(result.get(&14000).unwrap(), "普通的一行");
+    }
+
+    #[test]
+    fn test_parse_lyric_offset() {
+        let lyric = "[offset:500]\n[00:00.20]第一句\n[00:10.00]第二句";
+        let result = SearchLyricsInfo::parse_lyric(lyric).unwrap();
+        // 提前 500 毫秒，不会小于 0
+        assert_eq!(result.keys().copied().collect::<Vec<_>>(), vec![0, 9500]);
+
+        let lyric = "[offset:-300]\n[00:10.00]第一句";
+        let result = SearchLyricsInfo::parse_lyric(lyric).unwrap();
+        assert_eq!(result.keys().copied().collect::<Vec<_>>(), vec![10300]);
+    }
+
+    #[test]
+    fn test_parse_lyric_malformed_timestamps() {
+        let lyric = "[00:00.00]第一句\n[00:00.00]第二句\n[00:00.00]第三句\n[00:10.00]第四句";
+        assert!(SearchLyricsInfo::parse_lyric(lyric).is_err());
+
+        // 只有第一行在 0 秒是正常的
+        let lyric = "[00:00.00]玫瑰少年 - 蔡依林\n[00:10.00]第一句\n[00:20.00]第二句";
+        assert_eq!(SearchLyricsInfo::parse_lyric(lyric).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_dedup_adjacent_lines() {
+        let lyric = "[00:01.00]啊\n[00:01.10]啊\n[00:01.20]啊\n[00:02.00]第一句\n[00:05.00]第一句\n[00:06.00]啊";
+        let mut info = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric(lyric).unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        info.dedup_adjacent_lines(200);
+        let lines = info.lyrics.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>();
+        // 间隔较长的重复和不相邻的重复都保留
+        assert_eq!(
+            lines,
+            vec![(1000, "啊"), (2000, "第一句"), (5000, "第一句"), (6000, "啊")]
+        );
+    }
+
+    // 本地 HTTP 服务按顺序返回给定的状态码，返回服务地址和请求计数
+    #[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+    async fn status_server(statuses: Vec<u16>) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = format!("http://{}/", listener.local_addr().unwrap());
+        let count = std::sync::Arc::new(AtomicUsize::new(0));
+        let server_count = count.clone();
+        tokio::spawn(async move {
+            for status in statuses {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = [0; 1024];
+                let _ = stream.read(&mut buf).await;
+                server_count.fetch_add(1, Ordering::SeqCst);
+                let response = format!("HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        (addr, count)
+    }
+
+    #[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+    #[tokio::test]
+    async fn test_send_with_retry() {
+        use std::sync::atomic::Ordering;
+
+        let client = reqwest::Client::new();
+        // 5xx 重试直到成功
+        let (addr, count) = status_server(vec![503, 502, 200]).await;
+        let resp = send_with_retry(client.get(&addr), RetryPolicy { retries: 2, quiet: true }).await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        // 4xx 不重试
+        let (addr, count) = status_server(vec![404, 200]).await;
+        let resp = send_with_retry(client.get(&addr), RetryPolicy { retries: 2, quiet: true }).await.unwrap();
+        assert_eq!(resp.status(), 404);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_lyric_or_plain() {
+        let (lyrics, synced) = SearchLyricsInfo::parse_lyric_or_plain("[00:01.00]第一句").unwrap();
+        assert!(synced);
+        assert_eq!(lyrics.get(&1000).unwrap(), "第一句");
+
+        // 只有纯文本歌词，标签行去掉
+        let (lyrics, synced) =
+            SearchLyricsInfo::parse_lyric_or_plain("[ti:玫瑰少年]\n[ar:蔡依林]\n第一句\n\n第二句\n").unwrap();
+        assert!(!synced);
+        assert_eq!(lyrics, BTreeMap::from([(0, String::from("第一句\n\n第二句"))]));
+
+        assert!(SearchLyricsInfo::parse_lyric_or_plain("").is_err());
+        assert!(SearchLyricsInfo::parse_lyric_or_plain("[ti:玫瑰少年]\n").is_err());
+    }
+
+    #[test]
+    fn test_collapse_repeated_lines() {
+        let lyric = "[00:01.00]副歌\n[00:04.00]副歌\n[00:08.00]副歌\n[00:12.00]第二句\n[00:16.00]副歌";
+        let mut info = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric(lyric).unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        info.collapse_repeated_lines();
+        let lines = info.lyrics.iter().map(|(k, v)| (*k, v.as_str())).collect::<Vec<_>>();
+        assert_eq!(lines, vec![(1000, "副歌"), (12000, "第二句"), (16000, "副歌")]);
+    }
+
+    #[test]
+    fn test_is_instrumental() {
+        let markers = vec![String::from("纯音乐"), String::from("instrumental")];
+        let placeholder = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric("[00:00.00]此歌曲为没有填词的纯音乐，请您欣赏").unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        assert!(placeholder.is_instrumental(&markers));
+
+        // 正常歌词中提到纯音乐不算
+        let lyric = "[00:01.00]第一句\n[00:02.00]纯音乐\n[00:03.00]第三句\n[00:04.00]第四句";
+        let lyrics = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric(lyric).unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        assert!(!lyrics.is_instrumental(&markers));
+
+        // 没有时间轴的歌词只有一项，但中间有 [Instrumental] 标记也不算
+        let (lyrics, synced) =
+            SearchLyricsInfo::parse_lyric_or_plain("第一句\n[Instrumental]\n第三句\n第四句").unwrap();
+        let plain = SearchLyricsInfo {
+            source: String::from("lrclib"),
+            lyrics,
+            translations: BTreeMap::new(),
+            synced,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        assert!(!plain.is_instrumental(&markers));
+    }
+
+    #[test]
+    fn test_skip_credit_lines() {
+        let lyric = "[00:00.00]玫瑰少年 - 蔡依林\n[00:00.50] 作词 : 五月天阿信\n[00:01.00] 作曲 : Skot Suyama\n[00:02.00]Lyrics by someone\n[00:10.00]第一句\n[00:20.00]作曲：写在歌词中间";
+        let mut info = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric(lyric).unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        info.skip_credit_lines("玫瑰少年", "蔡依林");
+        let lines = info.lyrics.values().map(String::as_str).collect::<Vec<_>>();
+        assert_eq!(lines, vec!["第一句", "作曲：写在歌词中间"]);
+
+        // 只有制作信息时保留原样
+        let mut info = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: SearchLyricsInfo::parse_lyric("[00:00.00] 作词 : 五月天阿信").unwrap(),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        info.skip_credit_lines("玫瑰少年", "蔡依林");
+        assert_eq!(info.lyrics.len(), 1);
+    }
+
+    #[test]
+    fn test_parse_timestamp_fraction() {
+        assert_eq!(SearchLyricsInfo::parse_timestamp("01:02.3"), 62300);
+        assert_eq!(SearchLyricsInfo::parse_timestamp("01:02.34"), 62340);
+        assert_eq!(SearchLyricsInfo::parse_timestamp("01:02.345"), 62345);
+    }
+
     // #[test]
     // fn test_parse_lyric() {
     //     let lyric = qq::get_lyric("003QrvzS3248Wi");
@@ -10,7 +10,9 @@ use serde::Serialize;
 use serde_json::{json, Value};
 use anyhow::Result;
 
-use super::{LyricsProviderTrait, SearchLyricsInfo, REQWEST_TIMEOUT};
+use super::matching::{select_candidate, SongCandidate};
+use crate::utils::string::normalize_search_title;
+use super::{http_client, send_with_retry, LyricsProviderTrait, NotFound, ProviderCapabilities, RetryPolicy, SearchLyricsInfo};
 
 const BASE62_CHARSET: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
 const WEAPI_PRESET_KEY: &[u8] = b"0CoJUm6Qyw8W8jud";
@@ -22,10 +24,10 @@ const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 11_1_0) AppleWe
 // get 16 length secret from base62
 fn get_secret() -> [u8; 16] {
     let mut key = [0; 16];
-    let mut rng = rand::thread_rng();
-    for i in 0..16 {
-        let index = rng.gen_range(0..62);
-        key[i] = BASE62_CHARSET.as_bytes()[index];
+    let mut rng = rand::rng();
+    for byte in key.iter_mut() {
+        let index = rng.random_range(0..62);
+        *byte = BASE62_CHARSET.as_bytes()[index];
     }
     key
 }
@@ -43,7 +45,7 @@ fn do_rsa_with_reverse_secret(data: &[u8], to: &mut [u8; 128]) {
     let data = data.to_vec();
     let extend_data = [vec![0; 128 - data.len()], data].concat();
 
-    rsa.public_encrypt(&extend_data.as_slice(), to, Padding::NONE)
+    rsa.public_encrypt(extend_data.as_slice(), to, Padding::NONE)
         .unwrap();
 }
 
@@ -73,7 +75,8 @@ struct WeApiReqForm {
     encSecKey: String,
 }
 
-async fn get_lyric(id: &str) -> Result<String> {
+// 返回原歌词和翻译歌词，没有翻译时翻译为空
+async fn get_lyric(client: &reqwest::Client, retry: RetryPolicy, id: &str) -> Result<(String, String)> {
     let url = "https://music.163.com/weapi/song/lyric";
     let data = json!({
         "id": id,
@@ -84,25 +87,23 @@ async fn get_lyric(id: &str) -> Result<String> {
     });
     let req_form = weapi_encrypt(data);
 
-    let client = reqwest::Client::new();
-
-    let resp = client.post(url)
+    let request = client.post(url)
         .header("Content-Type", "application/x-www-form-urlencoded")
         .header("Referer", "https://music.163.com/")
         .header("User-Agent", USER_AGENT)
-        .form(&req_form)
-        .timeout(Duration::from_secs(REQWEST_TIMEOUT))
-        .send()
-        .await?;
-    let json: Value = resp.json()
-        .await?;
+        .form(&req_form);
+    let resp = send_with_retry(request, retry).await?;
+    let json = parse_response(&resp.text().await?)?;
     let lyric = json.pointer("/lrc/lyric")
-        .ok_or(anyhow::anyhow!("No lyric found"))?
+        .ok_or_else(|| NotFound(String::from("No lyric found")))?
         .as_str().unwrap();
-    Ok(lyric.to_string())
+    let translation = json.pointer("/tlyric/lyric")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    Ok((lyric.to_string(), translation.to_string()))
 }
 
-async fn search(keyword: &str) -> Result<Value> {
+async fn search(client: &reqwest::Client, retry: RetryPolicy, keyword: &str) -> Result<Value> {
     let url = "https://music.163.com/weapi/cloudsearch/pc";
     let data = json!({
         "s": keyword,
@@ -113,22 +114,68 @@ async fn search(keyword: &str) -> Result<Value> {
     });
     let req_form = weapi_encrypt(data);
 
-    let client = reqwest::Client::new();
-
-    let resp = client.post(url)
+    let request = client.post(url)
         .header("Content-Type", "application/x-www-form-urlencoded")
         .header("Referer", "https://music.163.com/")
         .header("User-Agent", USER_AGENT)
-        .form(&req_form)
-        .timeout(Duration::from_secs(REQWEST_TIMEOUT))
-        .send()
-        .await?;
+        .form(&req_form);
+    let resp = send_with_retry(request, retry).await?;
+
+    parse_response(&resp.text().await?)
+}
+
+// 网易在限流等情况下会返回 HTTP 200 的错误页面或错误码，先检查再取数据，避免报出看不懂的路径错误
+fn parse_response(text: &str) -> Result<Value> {
+    let json: Value = serde_json::from_str(text)
+        .map_err(|_| anyhow::anyhow!("Response is not JSON, possibly an error page"))?;
+    match json["code"].as_i64() {
+        None | Some(200) => Ok(json),
+        Some(-460) | Some(-461) => Err(anyhow::anyhow!("Rate limited by netease (code {})", json["code"])),
+        Some(301) => Err(anyhow::anyhow!("Login required (code 301)")),
+        Some(code) => Err(anyhow::anyhow!(
+            "Netease API error code {}: {}",
+            code,
+            json["message"].as_str().or(json["msg"].as_str()).unwrap_or_default()
+        )),
+    }
+}
 
-    let json: Value = resp.json().await?;
-    Ok(json)
+// 从搜索结果中选出最匹配的歌曲，返回歌曲 id 和时长差
+// 没有有效 id 的条目直接跳过，避免请求 "null" 之类的歌词
+fn find_best_match(
+    songs: &[Value],
+    title: &str,
+    length: u64,
+    min_title_similarity: f64,
+) -> Result<(u64, i64, f64)> {
+    let candidates = songs
+        .iter()
+        .filter_map(|song| {
+            Some(SongCandidate {
+                id: song["id"].as_u64().filter(|id| *id > 0)?,
+                title: song["name"].as_str().unwrap_or_default().to_string(),
+                length: song["dt"].as_u64(),
+            })
+        })
+        .collect();
+    select_candidate(candidates, title, length, min_title_similarity)
 }
 
-pub struct NeteaseLyricsProvider {}
+pub struct NeteaseLyricsProvider {
+    client: reqwest::Client,
+    min_title_similarity: f64,
+    retry: RetryPolicy,
+}
+
+impl NeteaseLyricsProvider {
+    pub fn new(min_title_similarity: f64, timeout: Duration, retry: RetryPolicy) -> Self {
+        NeteaseLyricsProvider {
+            client: http_client(timeout),
+            min_title_similarity,
+            retry,
+        }
+    }
+}
 
 #[async_trait]
 impl LyricsProviderTrait for NeteaseLyricsProvider {
@@ -136,33 +183,42 @@ impl LyricsProviderTrait for NeteaseLyricsProvider {
     fn get_source_name(&self) -> String {
         String::from("netease")
     }
-    async fn get_best_match_lyric(&self, keyword: &str, length: u64) -> Result<SearchLyricsInfo> {
-        let data = search(keyword).await?;
+    fn capabilities(&self) -> ProviderCapabilities {
+        ProviderCapabilities {
+            synced: true,
+            translation: true,
+            id_lookup: true,
+            ..Default::default()
+        }
+    }
+    async fn get_best_match_lyric(&self, title: &str, artist: &str, _album: &str, length: u64, _url: &str) -> Result<SearchLyricsInfo> {
+        // 搜索关键词去掉 feat./Remaster 等附加信息，匹配时仍使用原标题
+        let data = search(&self.client, self.retry, &format!("{} {}", artist, normalize_search_title(title))).await?;
+        // 没有搜索结果时不返回 songs 字段
         let all_song = data.pointer("/result/songs")
-            .ok_or(anyhow::anyhow!("No /result/songs path in json"))?
+            .ok_or_else(|| NotFound(String::from("No /result/songs path in json")))?
             .as_array()
             .ok_or(anyhow::anyhow!("Not an array"))?;
 
-        let mut match_song = all_song.first()
-            .ok_or(anyhow::anyhow!("No songs found"))?;
+        let (id, delta_abs, title_similarity) = find_best_match(all_song, title, length, self.min_title_similarity)?;
 
-        for song in all_song {
-            if song["dt"].as_u64().unwrap() == length {
-                match_song = song;
-                break;
-            }
-        }
-
-        let delta_abs = (match_song["dt"].as_i64().unwrap() - length as i64).abs();
-
-        let id = match_song["id"].to_string();
-        let lyric_text = get_lyric(id.as_str()).await?;
+        let mut lyrics = self.get_lyric_by_id(&id.to_string()).await?;
+        lyrics.delta_abs = delta_abs;
+        lyrics.title_similarity = title_similarity;
+        Ok(lyrics)
+    }
+    async fn get_lyric_by_id(&self, id: &str) -> Result<SearchLyricsInfo> {
+        let (lyric_text, translation_text) = get_lyric(&self.client, self.retry, id).await?;
+        let (lyrics, synced) = SearchLyricsInfo::parse_lyric_or_plain(&lyric_text)?;
 
         let lyrics = SearchLyricsInfo {
             source: String::from("netease"),
-            lyrics: SearchLyricsInfo::parse_lyric(&lyric_text),
-            // fallback,
-            delta_abs,
+            lyrics,
+            // 翻译有问题时不影响原歌词
+            translations: SearchLyricsInfo::parse_lyric(&translation_text).unwrap_or_default(),
+            synced,
+            delta_abs: 0,
+            title_similarity: 1.0,
         };
         Ok(lyrics)
     }
@@ -197,10 +253,51 @@ mod tests {
     // }
 
     #[test]
-    fn test_lyric() {
-        let lyric = get_lyric("191895");
+    fn test_parse_response_error_code() {
+        let err = parse_response(r#"{"code":-460,"message":"Cheating"}"#).unwrap_err();
+        assert!(err.to_string().contains("Rate limited"));
+        assert!(parse_response("<html>502 Bad Gateway</html>").is_err());
+        assert!(parse_response(r#"{"code":200,"result":{"songs":[]}}"#).is_ok());
+    }
+
+    #[test]
+    fn test_find_best_match_skips_invalid_id() {
+        let songs = json!([{ "id": null, "name": "玫瑰少年", "dt": 216000 }]);
+        assert!(find_best_match(songs.as_array().unwrap(), "玫瑰少年", 216000, 0.3).is_err());
+
+        let songs = json!([
+            { "id": 0, "name": "玫瑰少年", "dt": 216000 },
+            { "name": "玫瑰少年", "dt": 216000 },
+            { "id": 191895, "name": "玫瑰少年", "dt": 215000 }
+        ]);
+        assert_eq!(
+            find_best_match(songs.as_array().unwrap(), "玫瑰少年", 216000, 0.3).unwrap(),
+            (191895, 1000, 1.0)
+        );
+    }
+
+    #[test]
+    fn test_find_best_match_ignores_title_suffix() {
+        let songs = json!([
+            { "id": 1, "name": "Stay", "dt": 141000 },
+            { "id": 2, "name": "Yellow", "dt": 269000 }
+        ]);
+        let songs = songs.as_array().unwrap();
+        assert_eq!(find_best_match(songs, "Stay [ft. Justin Bieber]", 141000, 0.3).unwrap(), (1, 0, 1.0));
+        assert_eq!(
+            find_best_match(songs, "Yellow (Live at Glastonbury)", 269000, 0.3).unwrap(),
+            (2, 0, 1.0)
+        );
+    }
+
+    // 需要访问网易云，手动运行：cargo test -- --ignored
+    #[tokio::test]
+    #[ignore]
+    async fn test_lyric() {
+        let client = http_client(Duration::from_secs(crate::api::REQWEST_TIMEOUT));
+        let lyric = get_lyric(&client, RetryPolicy::default(), "191895").await;
         match lyric {
-            Ok(lyric) => println!("{}", lyric),
+            Ok((lyric, _)) => println!("{}", lyric),
             Err(e) => println!("{:?}", e),
         }
     }
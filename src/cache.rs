@@ -0,0 +1,236 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::SearchLyricsInfo;
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct CacheConfig {
+    pub enabled: bool,
+    // 缓存的歌词保留天数
+    pub ttl_days: u64,
+    // 所有歌词源都没有找到歌词时也会缓存，保留时间较短，过期后重新搜索
+    pub negative_ttl_hours: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: true,
+            ttl_days: 30,
+            negative_ttl_hours: 1,
+        }
+    }
+}
+
+/// 歌词磁盘缓存，每首歌一个 .lrc 文件，开头用标签记录歌词来源等信息
+pub struct LyricsCache {
+    dir: PathBuf,
+    ttl: Duration,
+    negative_ttl: Duration,
+}
+
+// 缓存键，用 FNV-1a 计算，保证不同版本编译出来的程序结果一致
+pub fn cache_key(title: &str, artist: &str, album: &str, length: u64) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let text = format!("{}\0{}\0{}\0{}", title, artist, album, length);
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+fn format_timestamp(timestamp: u64) -> String {
+    format!(
+        "{:02}:{:02}.{:03}",
+        timestamp / 60000,
+        timestamp / 1000 % 60,
+        timestamp % 1000
+    )
+}
+
+impl LyricsCache {
+    pub fn new(dir: PathBuf, config: &CacheConfig) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(LyricsCache {
+            dir,
+            ttl: Duration::from_secs(config.ttl_days * 24 * 60 * 60),
+            negative_ttl: Duration::from_secs(config.negative_ttl_hours * 60 * 60),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.lrc", key))
+    }
+
+    // 未缓存或已过期返回 None，缓存了没有歌词返回 Some(None)
+    pub fn load(&self, key: &str) -> Option<Option<SearchLyricsInfo>> {
+        let path = self.path(key);
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        let content = fs::read_to_string(&path).ok()?;
+
+        let mut source = None;
+        let mut delta_abs = 0;
+        let mut synced = true;
+        let mut text = Vec::new();
+        let mut translation_text = Vec::new();
+        for line in content.lines() {
+            if let Some(translation) = line.strip_prefix("[tr]") {
+                translation_text.push(translation);
+            } else if let Some(value) = line
+                .strip_prefix("[source:")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                source = Some(value.to_string());
+            } else if let Some(value) = line
+                .strip_prefix("[delta:")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                delta_abs = value.parse().unwrap_or_default();
+            } else if line == "[synced:false]" {
+                synced = false;
+            } else {
+                text.push(line);
+            }
+        }
+
+        let ttl = if source.is_some() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        if age > ttl {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        let Some(source) = source else {
+            return Some(None);
+        };
+        let lyrics = if synced {
+            SearchLyricsInfo::parse_lyric(&text.join("\n")).ok()?
+        } else {
+            [(0, text.join("\n"))].into()
+        };
+        Some(Some(SearchLyricsInfo {
+            source,
+            lyrics,
+            translations: SearchLyricsInfo::parse_lyric(&translation_text.join("\n")).unwrap_or_default(),
+            synced,
+            delta_abs,
+            title_similarity: 1.0,
+        }))
+    }
+
+    // 传入 None 表示所有歌词源都没有找到歌词
+    pub fn store(
+        &self,
+        key: &str,
+        search_lyrics_info: Option<&SearchLyricsInfo>,
+    ) -> io::Result<()> {
+        let mut content = String::new();
+        if let Some(info) = search_lyrics_info {
+            content.push_str(&format!(
+                "[source:{}]\n[delta:{}]\n",
+                info.source, info.delta_abs
+            ));
+            if info.synced {
+                for (timestamp, lyric) in &info.lyrics {
+                    content.push_str(&format!("[{}]{}\n", format_timestamp(*timestamp), lyric));
+                }
+                // 翻译加上 [tr] 前缀，解析原歌词时会被跳过
+                for (timestamp, translation) in &info.translations {
+                    content.push_str(&format!("[tr][{}]{}\n", format_timestamp(*timestamp), translation));
+                }
+            } else {
+                content.push_str("[synced:false]\n");
+                for lyric in info.lyrics.values() {
+                    content.push_str(lyric);
+                    content.push('\n');
+                }
+            }
+        }
+        fs::write(self.path(key), content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn test_cache(name: &str, config: &CacheConfig) -> LyricsCache {
+        let dir =
+            std::env::temp_dir().join(format!("mpris-lyrics-rs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        LyricsCache::new(dir, config).unwrap()
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let cache = test_cache("round-trip", &CacheConfig::default());
+        let key = cache_key("玫瑰少年", "蔡依林", "Ugly Beauty", 216000);
+        assert!(cache.load(&key).is_none());
+
+        let info = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: BTreeMap::from([
+                (1000, String::from("第一句")),
+                (61500, String::from("第二句")),
+            ]),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 1000,
+            title_similarity: 1.0,
+        };
+        cache.store(&key, Some(&info)).unwrap();
+        let cached = cache.load(&key).unwrap().unwrap();
+        assert_eq!(cached.source, "netease");
+        assert_eq!(cached.lyrics, info.lyrics);
+        assert_eq!(cached.translations, info.translations);
+        assert_eq!(cached.delta_abs, 1000);
+
+        // 纯文本歌词
+        let plain = SearchLyricsInfo {
+            source: String::from("lrclib"),
+            lyrics: BTreeMap::from([(0, String::from("第一句\n第二句"))]),
+            translations: BTreeMap::new(),
+            synced: false,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        cache.store(&key, Some(&plain)).unwrap();
+        let cached = cache.load(&key).unwrap().unwrap();
+        assert!(!cached.synced);
+        assert_eq!(cached.lyrics, plain.lyrics);
+
+        // 没有歌词
+        cache.store(&key, None).unwrap();
+        assert!(cache.load(&key).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cache_expired() {
+        let cache = test_cache(
+            "expired",
+            &CacheConfig {
+                enabled: true,
+                ttl_days: 30,
+                negative_ttl_hours: 0,
+            },
+        );
+        let key = cache_key("玫瑰少年", "蔡依林", "", 0);
+        cache.store(&key, None).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        // 过期的缓存被删除
+        assert!(cache.load(&key).is_none());
+        assert!(!cache.path(&key).exists());
+    }
+}
@@ -0,0 +1,97 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use zbus::interface;
+
+use crate::NowPlaying;
+
+const SERVICE_NAME: &str = "org.mprislyrics.Display";
+const OBJECT_PATH: &str = "/org/mprislyrics/Display";
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct DbusConfig {
+    // 在 session bus 上发布 org.mprislyrics.Display 服务
+    pub publish: bool,
+}
+
+/// 对外暴露的歌词状态，属性变化时发出 PropertiesChanged 信号
+struct Display {
+    now_playing: NowPlaying,
+}
+
+#[interface(name = "org.mprislyrics.Display")]
+impl Display {
+    #[zbus(property)]
+    fn line(&self) -> String {
+        self.now_playing.line.clone()
+    }
+
+    #[zbus(property)]
+    fn translation(&self) -> String {
+        self.now_playing.translation.clone()
+    }
+
+    #[zbus(property)]
+    fn transliteration(&self) -> String {
+        self.now_playing.transliteration.clone()
+    }
+
+    #[zbus(property)]
+    fn source(&self) -> String {
+        self.now_playing.source.clone()
+    }
+
+    #[zbus(property)]
+    fn title(&self) -> String {
+        self.now_playing.title.clone()
+    }
+
+    #[zbus(property)]
+    fn artist(&self) -> String {
+        self.now_playing.artist.clone()
+    }
+}
+
+// 连接断开时 bus 会自动释放服务名，进程退出时不需要额外注销
+pub async fn serve(mut now_playing: watch::Receiver<NowPlaying>) -> Result<()> {
+    let display = Display {
+        now_playing: now_playing.borrow_and_update().clone(),
+    };
+    let connection = zbus::connection::Builder::session()?
+        .name(SERVICE_NAME)?
+        .serve_at(OBJECT_PATH, display)?
+        .build()
+        .await?;
+    let display = connection
+        .object_server()
+        .interface::<_, Display>(OBJECT_PATH)
+        .await?;
+
+    loop {
+        now_playing.changed().await?;
+        let current = now_playing.borrow_and_update().clone();
+        let mut iface = display.get_mut().await;
+        let previous = std::mem::replace(&mut iface.now_playing, current);
+        // 播放进度每秒都在变，只在对外暴露的属性变化时发信号
+        let emitter = display.signal_emitter();
+        if previous.line != iface.now_playing.line {
+            iface.line_changed(emitter).await?;
+        }
+        if previous.translation != iface.now_playing.translation {
+            iface.translation_changed(emitter).await?;
+        }
+        if previous.transliteration != iface.now_playing.transliteration {
+            iface.transliteration_changed(emitter).await?;
+        }
+        if previous.source != iface.now_playing.source {
+            iface.source_changed(emitter).await?;
+        }
+        if previous.title != iface.now_playing.title {
+            iface.title_changed(emitter).await?;
+        }
+        if previous.artist != iface.now_playing.artist {
+            iface.artist_changed(emitter).await?;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::os::unix::fs::FileTypeExt;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::watch;
+
+use crate::NowPlaying;
+
+#[derive(Deserialize, Serialize, Clone, Default)]
+#[serde(default)]
+pub struct IpcConfig {
+    // Unix socket 路径，为空时不启用
+    pub socket_path: String,
+}
+
+/// 推送给脚本的歌词状态，每行一个 JSON
+#[derive(Serialize, PartialEq)]
+struct IpcUpdate<'a> {
+    title: &'a str,
+    artist: &'a str,
+    line: &'a str,
+    next_line: &'a str,
+    position_ms: u64,
+    source: &'a str,
+}
+
+impl<'a> IpcUpdate<'a> {
+    fn new(now_playing: &'a NowPlaying) -> Self {
+        IpcUpdate {
+            title: &now_playing.title,
+            artist: &now_playing.artist,
+            line: &now_playing.line,
+            next_line: &now_playing.next_line,
+            position_ms: now_playing.position,
+            source: &now_playing.source,
+        }
+    }
+
+    // 只在歌词或曲目变化时推送，播放进度每秒都在变，不单独推送
+    fn same_lyric(&self, other: &IpcUpdate) -> bool {
+        IpcUpdate { position_ms: 0, ..*self } == IpcUpdate { position_ms: 0, ..*other }
+    }
+}
+
+pub async fn serve(path: &str, now_playing: watch::Receiver<NowPlaying>) -> Result<()> {
+    // 上次退出时留下的 socket 文件需要先删除，其他类型的文件不动，交给 bind 报错
+    if fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let now_playing = now_playing.clone();
+        tokio::spawn(async move {
+            // 客户端断开属于正常情况，不打印错误
+            let _ = handle_connection(stream, now_playing).await;
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: UnixStream,
+    mut now_playing: watch::Receiver<NowPlaying>,
+) -> Result<()> {
+    // 先推送一次当前状态，之后每次歌词变化时推送
+    let mut last_sent: Option<NowPlaying> = None;
+    loop {
+        let current = now_playing.borrow_and_update().clone();
+        let update = IpcUpdate::new(&current);
+        if !last_sent
+            .as_ref()
+            .is_some_and(|last| IpcUpdate::new(last).same_lyric(&update))
+        {
+            let mut data = serde_json::to_string(&update)?;
+            data.push('\n');
+            stream.write_all(data.as_bytes()).await?;
+            stream.flush().await?;
+            last_sent = Some(current);
+        }
+        now_playing.changed().await?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn test_stream_updates() {
+        let path = std::env::temp_dir().join("mpris-lyrics-rs-ipc-test.sock");
+        let path = path.to_str().unwrap().to_string();
+        let (sender, receiver) = watch::channel(NowPlaying {
+            title: String::from("玫瑰少年"),
+            line: String::from("第一句"),
+            next_line: String::from("第二句"),
+            ..Default::default()
+        });
+        let server_path = path.clone();
+        tokio::spawn(async move { serve(&server_path, receiver).await });
+
+        let stream = loop {
+            match UnixStream::connect(&path).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        let mut lines = BufReader::new(stream).lines();
+        let first = lines.next_line().await.unwrap().unwrap();
+        assert!(first.contains(r#""line":"第一句","next_line":"第二句""#));
+
+        // 只有播放进度变化时不推送
+        sender.send_modify(|now_playing| now_playing.position = 1000);
+        sender.send_modify(|now_playing| now_playing.line = String::from("第二句"));
+        let second = lines.next_line().await.unwrap().unwrap();
+        assert!(second.contains(r#""line":"第二句""#));
+        assert!(second.contains(r#""position_ms":1000"#));
+        fs::remove_file(&path).unwrap();
+    }
+}
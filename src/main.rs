@@ -1,13 +1,33 @@
-use std::sync::{Arc, Mutex};
+use std::io::{IsTerminal, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
 use std::{fs, thread};
-use std::collections::BTreeMap;
-use std::time::Duration;
-use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
 use mpris::PlayerFinder;
+use tokio::sync::watch;
+use unicode_width::UnicodeWidthStr;
 
 mod api;
+mod cache;
+#[cfg(feature = "dbus")]
+mod dbus;
+#[cfg(feature = "ipc")]
+mod ipc;
+mod overrides;
+mod utils;
+#[cfg(feature = "web")]
+mod web;
 
-use crate::api::LyricsProviderTrait;
+use crate::api::{LyricsProviderTrait, SearchLyricsInfo};
+use crate::utils::translit::{transliterate, Transliteration};
+
+// 歌词显示线程超过这个时间没有心跳，认为已经卡死
+const DISPLAY_HEARTBEAT_TIMEOUT: u64 = 30000;
+// 切歌时 metadata 不全，最多等待播放器补全的时间
+const METADATA_SETTLE_TIMEOUT: u64 = 1000;
 
 struct SharedData {
     current_player_name: Arc<Mutex<String>>,
@@ -20,57 +40,989 @@ struct LyricsInfo {
     artist: String,
     length: u64,
     lyrics: BTreeMap<u64, String>,
+    translations: BTreeMap<u64, String>,
+    // 每行歌词的注音，未开启 transliteration 时为空
+    transliterations: BTreeMap<u64, String>,
+    // 纯文本歌词的全文，带时间轴时为空
+    plain_lyrics: String,
+    // 当前歌词的来源
+    source: String,
     last_printed_line: String,
+    // 上一次输出的 JSON，内容不变时不重复输出
+    last_output: String,
+}
+
+/// 当前播放状态，推送给网页歌词等外部展示
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+struct NowPlaying {
+    title: String,
+    artist: String,
+    line: String,
+    // 当前行的翻译，未开启 show_translation 或没有翻译时为空
+    translation: String,
+    // 当前行的注音，未开启 transliteration 或当前行不需要注音时为空
+    transliteration: String,
+    next_line: String,
+    // 纯文本歌词的全文，网页歌词整段显示，line 仍然只有一行
+    plain_lyrics: String,
+    source: String,
+    position: u64,
+    length: u64,
 }
 
-#[derive(Deserialize)]
+/// 用于判断是否切歌的曲目标识
+///
+/// 不提供 `mpris:trackid` 的播放器只能靠标签区分曲目，同标签的两首歌（如两段未命名录音）
+/// 会被误判为同一首，所以这里额外比较时长和 `xesam:url`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct TrackKey {
+    track_id: String,
+    title: String,
+    artist: String,
+    album: String,
+    length: u64,
+    url: String,
+}
+
+impl TrackKey {
+    fn from_metadata(metadata: &mpris::Metadata) -> Self {
+        TrackKey {
+            track_id: metadata
+                .track_id()
+                .filter(|id| *id != mpris::TrackID::no_track())
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            title: metadata.title().unwrap_or_default().to_string(),
+            artist: metadata.artists().unwrap_or_default().join(","),
+            album: metadata.album_name().unwrap_or_default().to_string(),
+            length: metadata.length().map(|d| d.as_millis() as u64).unwrap_or(0),
+            url: metadata.url().unwrap_or_default().to_string(),
+        }
+    }
+
+    // 缺少歌手或时长，部分播放器切歌时先发只有标题的 metadata，稍后再补全
+    fn is_sparse(&self) -> bool {
+        self.artist.is_empty() || self.length == 0
+    }
+}
+
+/// 切歌时等待不完整的 metadata 稳定下来，避免先用只有标题的信息搜到错误的歌词，补全后又马上重新搜索
+#[derive(Default)]
+struct MetadataSettle {
+    pending: Option<(TrackKey, u64)>,
+}
+
+impl MetadataSettle {
+    // 返回是否还需要等待，metadata 完整或等待超时后不再等待
+    fn should_wait(&mut self, track: &TrackKey, now: u64) -> bool {
+        if !track.is_sparse() {
+            self.pending = None;
+            return false;
+        }
+        match &self.pending {
+            Some((pending, since)) if pending == track => {
+                now.saturating_sub(*since) < METADATA_SETTLE_TIMEOUT
+            }
+            _ => {
+                self.pending = Some((track.clone(), now));
+                true
+            }
+        }
+    }
+}
+
+/// 部分播放器（如一些网页播放器）不提供播放进度，此时按实际经过的时间估算
+#[derive(Default)]
+struct PositionEstimate {
+    // 单位毫秒，updated_at 为 0 表示还没有开始估算
+    position: u64,
+    updated_at: u64,
+}
+
+impl PositionEstimate {
+    // 播放器报告了播放进度，以它为准
+    fn sync(&mut self, position: u64, now: u64) {
+        self.position = position;
+        self.updated_at = now;
+    }
+
+    // 播放时加上经过的时间，暂停期间只更新时间，不计入进度
+    fn advance(&mut self, playing: bool, now: u64) -> u64 {
+        if playing && self.updated_at > 0 {
+            self.position += now.saturating_sub(self.updated_at);
+        }
+        self.updated_at = now;
+        self.position
+    }
+
+    fn reset(&mut self) {
+        *self = PositionEstimate::default();
+    }
+}
+
+// 配置文件中缺少的项使用默认值，新版本增加配置项时旧的配置文件仍然可以使用
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
 struct Config {
     player_refresh_interval: u64,
     lyric_refresh_interval: u64,
     white_list: Vec<String>,
+    // 播放器别名，同一个播放器的不同名字，如 "mpd" = ["Music Player Daemon"]
+    player_aliases: HashMap<String, Vec<String>>,
     sort_list: Vec<String>,
+    prefer_synced: bool,
+    // 只输出歌词，不输出曲目信息等提示
+    quiet: bool,
+    // 离线模式，不请求任何网络歌词源
+    offline: bool,
+    // 单个歌词源获取歌词的总时间上限，单位毫秒，超时按失败处理，继续请求下一个歌词源
+    provider_timeout: u64,
+    polling: PollingConfig,
+    matching: MatchingConfig,
+    // 按歌词源名称覆盖的配置
+    sources: HashMap<String, api::SourceConfig>,
+    lyrics: LyricsConfig,
+    display: DisplayConfig,
+    cache: cache::CacheConfig,
+    // 外部命令歌词源，配置了命令时启用
+    command: api::command::CommandConfig,
+    // 按曲目指定歌词的配置文件，相对路径相对于配置目录，为空时不启用
+    overrides: String,
+    // 本地音频文件内嵌的歌词
+    #[cfg(feature = "embedded")]
+    embedded: api::embedded::EmbeddedConfig,
+    #[cfg(feature = "web")]
+    web: web::WebConfig,
+    #[cfg(feature = "dbus")]
+    dbus: dbus::DbusConfig,
+    #[cfg(feature = "ipc")]
+    ipc: ipc::IpcConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            player_refresh_interval: 3000,
+            lyric_refresh_interval: 50,
+            white_list: vec![
+                String::from("mpd"),
+                String::from("Spotify"),
+                String::from("lx-music-desktop"),
+            ],
+            player_aliases: HashMap::new(),
+            sort_list: vec![
+                String::from("embedded"),
+                String::from("netease"),
+                String::from("qq"),
+                String::from("lrclib"),
+            ],
+            prefer_synced: true,
+            quiet: false,
+            offline: false,
+            provider_timeout: 10000,
+            polling: PollingConfig::default(),
+            matching: MatchingConfig::default(),
+            sources: HashMap::new(),
+            lyrics: LyricsConfig::default(),
+            display: DisplayConfig::default(),
+            cache: cache::CacheConfig::default(),
+            command: api::command::CommandConfig::default(),
+            overrides: String::new(),
+            #[cfg(feature = "embedded")]
+            embedded: api::embedded::EmbeddedConfig::default(),
+            #[cfg(feature = "web")]
+            web: web::WebConfig::default(),
+            #[cfg(feature = "dbus")]
+            dbus: dbus::DbusConfig::default(),
+            #[cfg(feature = "ipc")]
+            ipc: ipc::IpcConfig::default(),
+        }
+    }
+}
+
+/// 空闲时的轮询配置，有播放器时按 player_refresh_interval 轮询
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct PollingConfig {
+    // 没有播放器时的轮询间隔，从最小值开始，每次翻倍直到最大值
+    idle_min_interval: u64,
+    idle_max_interval: u64,
+}
+
+impl Default for PollingConfig {
+    fn default() -> Self {
+        PollingConfig {
+            idle_min_interval: 5000,
+            idle_max_interval: 30000,
+        }
+    }
+}
+
+/// 歌曲匹配配置
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct MatchingConfig {
+    // 标题相似度低于该值的搜索结果直接排除
+    min_title_similarity: f64,
+    // 搜索时使用的歌手字段，显示时仍然使用全部歌手
+    artist_field: ArtistField,
+    // 请求歌词源的策略
+    strategy: FetchStrategy,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> Self {
+        MatchingConfig {
+            min_title_similarity: 0.3,
+            artist_field: ArtistField::All,
+            strategy: FetchStrategy::Best,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ArtistField {
+    // 所有歌手，用逗号连接
+    All,
+    // 只用第一位歌手，合唱、feat. 较多的歌曲更容易搜到
+    First,
+    // 专辑歌手，播放器没有提供时使用所有歌手
+    AlbumArtist,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum FetchStrategy {
+    // 按 sort_list 顺序请求，使用第一个成功的结果，速度快，开启 prefer_synced 时跳过纯文本歌词
+    First,
+    // 请求所有歌词源，按是否带时间轴、标题相似度、时长差、是否有翻译和 sort_list 选出最佳结果
+    Best,
+}
+
+// 根据配置选出搜索歌词时使用的歌手
+fn search_artist(metadata: &mpris::Metadata, field: ArtistField) -> String {
+    let artists = metadata.artists().unwrap_or_default();
+    match field {
+        ArtistField::All => artists.join(","),
+        ArtistField::First => artists.first().copied().unwrap_or_default().to_string(),
+        ArtistField::AlbumArtist => metadata
+            .album_artists()
+            .filter(|album_artists| !album_artists.is_empty())
+            .unwrap_or(artists)
+            .join(","),
+    }
+}
+
+/// 歌词后处理配置
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct LyricsConfig {
+    // 合并时间间隔很短的相邻重复歌词
+    dedup_adjacent: bool,
+    // 相邻重复歌词的最大间隔，单位毫秒
+    dedup_window: u64,
+    // 不论间隔多长都合并相邻的重复歌词，开启后 dedup_adjacent 不再起作用
+    collapse_repeats: bool,
+    // 去掉歌词开头的作词、作曲等制作信息
+    skip_credit_lines: bool,
+    // 歌词只有寥寥几行且包含这些内容时认为是纯音乐
+    instrumental_markers: Vec<String>,
+}
+
+impl Default for LyricsConfig {
+    fn default() -> Self {
+        LyricsConfig {
+            dedup_adjacent: false,
+            dedup_window: 200,
+            collapse_repeats: false,
+            skip_credit_lines: false,
+            instrumental_markers: vec![String::from("纯音乐"), String::from("instrumental")],
+        }
+    }
+}
+
+/// 没有歌词可显示时推送给网页歌词等外部展示的提示
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+struct DisplayConfig {
+    // 没有播放器
+    idle_message: String,
+    // 有播放器但已停止播放
+    stopped_message: String,
+    // 纯音乐，替换歌词源返回的 "纯音乐，请欣赏" 之类的占位歌词
+    instrumental_message: String,
+    // 在歌词下方显示翻译，目前只有网易云提供翻译
+    show_translation: bool,
+    // 在歌词下方显示注音: none、pinyin（汉字转拼音）、romaji（假名转罗马音）
+    transliteration: Transliteration,
+    // 终端输出格式
+    output_format: OutputFormat,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            idle_message: String::from("等待播放器..."),
+            stopped_message: String::from("没有正在播放的歌曲"),
+            instrumental_message: String::from("🎵 纯音乐"),
+            show_translation: false,
+            transliteration: Transliteration::None,
+            output_format: OutputFormat::Line,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OutputFormat {
+    // 每行输出一句歌词
+    Line,
+    // 每行输出一个 JSON，供 waybar 的 custom 模块使用
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "line" => Ok(OutputFormat::Line),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("未知输出格式: {}，可选 line、json", value)),
+        }
+    }
+}
+
+/// waybar custom 模块的输出
+#[derive(Serialize)]
+struct WaybarOutput<'a> {
+    text: &'a str,
+    tooltip: String,
+    class: &'a str,
+}
+
+const USAGE: &str = "\
+用法: mpris-lyrics-rs [选项]
+
+选项:
+  -q, --quiet                   不输出提示信息
+      --offline                 只使用本地歌词源
+      --output-format <FORMAT>  输出格式: line 或 json
+      --build-info              打印版本、编译信息和配置文件路径
+      --list-players            列出当前的 MPRIS 播放器
+      --migrate-config          把缺少的配置项补进配置文件
+  -h, --help                    打印帮助信息
+  -V, --version                 打印版本号
+";
+
+/// 命令行参数
+#[derive(Debug, Default, PartialEq)]
+struct Args {
+    help: bool,
+    version: bool,
+    quiet: bool,
+    offline: bool,
+    build_info: bool,
+    list_players: bool,
+    migrate_config: bool,
+    output_format: Option<OutputFormat>,
+}
+
+impl Args {
+    fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut result = Args::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-h" | "--help" => result.help = true,
+                "-V" | "--version" => result.version = true,
+                "-q" | "--quiet" => result.quiet = true,
+                "--offline" => result.offline = true,
+                "--build-info" => result.build_info = true,
+                "--list-players" => result.list_players = true,
+                "--migrate-config" => result.migrate_config = true,
+                "--output-format" => {
+                    let value = args.next().ok_or("--output-format 缺少参数")?;
+                    result.output_format = Some(value.parse()?);
+                }
+                _ => return Err(format!("未知参数: {}\n使用 --help 查看可用参数", arg)),
+            }
+        }
+        Ok(result)
+    }
+}
+
+// 把缺少的配置项按默认值补进配置文件，保留用户原有的值和注释
+fn migrate_config(content: &str) -> anyhow::Result<String> {
+    let config: Config = toml::from_str(content)?;
+    let resolved: toml_edit::DocumentMut = toml::to_string(&config)?.parse()?;
+    let mut document: toml_edit::DocumentMut = content.parse()?;
+    merge_missing_items(document.as_table_mut(), resolved.as_table());
+    Ok(document.to_string())
+}
+
+fn merge_missing_items(target: &mut toml_edit::Table, source: &toml_edit::Table) {
+    for (key, item) in source.iter() {
+        match target.get_mut(key) {
+            Some(existing) => {
+                if let (Some(existing), Some(item)) = (existing.as_table_mut(), item.as_table()) {
+                    merge_missing_items(existing, item);
+                }
+            }
+            // 空的表（如没有配置任何歌词源的 sources）不写入
+            None if item.as_table().is_some_and(|table| table.is_empty()) => {}
+            None => {
+                target.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+// 打印版本、编译信息和配置文件路径，方便反馈问题，不依赖配置文件能否正常读取
+fn print_build_info(xdg_dir: &xdg::BaseDirectories) {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    println!("git: {}", option_env!("GIT_SHA").unwrap_or("unknown"));
+    let mut providers = get_all_providers(&MatchingConfig::default(), &HashMap::new(), true);
+    // 内嵌歌词和外部命令歌词源不在 get_all_providers 中，按配置启用
+    #[cfg(feature = "embedded")]
+    providers.push(Box::new(api::embedded::EmbeddedProvider));
+    providers.push(Box::new(api::command::CommandProvider::new(
+        api::command::CommandConfig::default(),
+    )));
+    println!("歌词源:");
+    for provider in providers {
+        println!(
+            "  {} ({})",
+            provider.get_source_name(),
+            provider.capabilities().names().join(", ")
+        );
+    }
+    let features = [
+        ("netease", cfg!(feature = "netease")),
+        ("qqmusic", cfg!(feature = "qqmusic")),
+        ("lrclib", cfg!(feature = "lrclib")),
+        ("embedded", cfg!(feature = "embedded")),
+        ("web", cfg!(feature = "web")),
+        ("dbus", cfg!(feature = "dbus")),
+        ("ipc", cfg!(feature = "ipc")),
+    ]
+    .iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| *name)
+    .collect::<Vec<_>>();
+    println!("功能: {}", features.join(", "));
+    match xdg_dir.find_config_file("config.toml") {
+        Some(path) => println!("配置文件: {}", path.display()),
+        None => println!(
+            "配置文件: {} (不存在)",
+            xdg_dir.get_config_file("config.toml").display()
+        ),
+    }
+}
+
+// 列出所有播放器，不经过白名单过滤，方便配置 white_list
+fn list_players() -> Result<(), mpris::FindingError> {
+    let players = PlayerFinder::new()?.find_all().or_else(|err| match err {
+        mpris::FindingError::NoPlayerFound => Ok(Vec::new()),
+        err => Err(err),
+    })?;
+    let mut rows = vec![[
+        String::from("名称"),
+        String::from("总线名"),
+        String::from("状态"),
+        String::from("曲目"),
+    ]];
+    for player in &players {
+        let status = player
+            .get_playback_status()
+            .map(|status| format!("{:?}", status))
+            .unwrap_or_else(|_| String::from("-"));
+        let track = player
+            .get_metadata()
+            .ok()
+            .and_then(|metadata| {
+                let title = metadata.title()?.to_string();
+                let artists = metadata.artists().unwrap_or_default().join(",");
+                Some(if artists.is_empty() { title } else { format!("{} - {}", artists, title) })
+            })
+            .unwrap_or_else(|| String::from("-"));
+        rows.push([
+            player.identity().to_string(),
+            player.bus_name().to_string(),
+            status,
+            track,
+        ]);
+    }
+    print!("{}", format_table(&rows));
+    Ok(())
+}
+
+// 按终端显示宽度对齐（中文占两列），最后一列不补空格
+fn format_table<const N: usize>(rows: &[[String; N]]) -> String {
+    let mut widths = [0; N];
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.width());
+        }
+    }
+    let mut output = String::new();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            if index + 1 < N {
+                let padding = widths[index] - cell.width() + 2;
+                output.push_str(cell);
+                output.push_str(&" ".repeat(padding));
+            } else {
+                output.push_str(cell);
+            }
+        }
+        output.push('\n');
+    }
+    output
+}
+
+// 统一大小写并去掉空格和标点，"LX Music"、"lx-music" 视为同一个名字
+fn normalize_player_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// 按 player_aliases 解析成统一的名字，不在别名表中的名字只做规范化
+fn resolve_player_name(name: &str, aliases: &HashMap<String, Vec<String>>) -> String {
+    let name = normalize_player_name(name);
+    aliases
+        .iter()
+        .map(|(canonical, names)| (normalize_player_name(canonical), names))
+        .find(|(canonical, names)| {
+            *canonical == name || names.iter().any(|alias| normalize_player_name(alias) == name)
+        })
+        .map(|(canonical, _)| canonical)
+        .unwrap_or(name)
+}
+
+// 总线名中的播放器名，去掉 MPRIS 前缀和浏览器等多实例播放器的 .instance 后缀
+// org.mpris.MediaPlayer2.firefox.instance_1_92 -> firefox
+fn bus_name_player_part(bus_name: &str) -> &str {
+    let name = bus_name.trim_start_matches("org.mpris.MediaPlayer2.");
+    name.find(".instance").map_or(name, |index| &name[..index])
+}
+
+// 播放器的 Identity 或总线名中的播放器名，和白名单中的名字解析后相同时匹配
+fn is_player_match(
+    white_list_name: &str,
+    identity: &str,
+    bus_name: &str,
+    aliases: &HashMap<String, Vec<String>>,
+) -> bool {
+    let expected = resolve_player_name(white_list_name, aliases);
+    resolve_player_name(identity, aliases) == expected
+        || resolve_player_name(bus_name_player_part(bus_name), aliases) == expected
 }
 
 fn find_current_player(
     finder: &PlayerFinder,
     white_list: &Vec<String>,
+    aliases: &HashMap<String, Vec<String>>,
 ) -> Result<mpris::Player, mpris::FindingError> {
+    // 优先选择正在播放的白名单播放器，都没有在播放时选择第一个存在的白名单播放器，
+    // 暂停时保持选中，恢复播放后歌词能马上跟上
+    let mut players = finder.find_all()?;
+    let mut fallback = None;
     // 遍历 white list
     for player_name in white_list {
-        // 查找当前所有正在播放音频的player, 检查是否存在白名单关键字
-        let players = finder.find_all()?;
-        for player in players {
-            if player
-                .identity()
-                .to_ascii_lowercase()
-                .contains(&player_name.to_ascii_lowercase())
-                && player.get_playback_status()? == mpris::PlaybackStatus::Playing
-            {
-                return Ok(player);
+        for (index, player) in players.iter().enumerate() {
+            if is_player_match(
+                player_name,
+                player.identity(),
+                player.bus_name(),
+                aliases,
+            ) {
+                // 单个播放器没有响应时跳过它，不影响其他播放器
+                match player.get_playback_status() {
+                    Ok(mpris::PlaybackStatus::Playing) => return Ok(players.swap_remove(index)),
+                    Ok(_) => {
+                        fallback.get_or_insert(index);
+                    }
+                    Err(_) => {}
+                }
             }
         }
     }
     // 如果没有找到，抛出异常，以便后续接收
-    Err(mpris::FindingError::NoPlayerFound)
+    fallback
+        .map(|index| players.swap_remove(index))
+        .ok_or(mpris::FindingError::NoPlayerFound)
+}
+
+// 指数退避的下一个间隔
+fn next_backoff(current: u64, max: u64) -> u64 {
+    current.saturating_mul(2).min(max)
+}
+
+// 连接 D-Bus，失败时按指数退避重试
+fn connect_player_finder(polling: &PollingConfig) -> PlayerFinder {
+    let mut interval = polling.idle_min_interval;
+    loop {
+        match PlayerFinder::new() {
+            Ok(finder) => return finder,
+            Err(err) => {
+                eprintln!("连接 D-Bus 失败: {}，{} 毫秒后重试", err, interval);
+                thread::sleep(Duration::from_millis(interval));
+                interval = next_backoff(interval, polling.idle_max_interval);
+            }
+        }
+    }
+}
+
+// 当前 unix 时间，单位毫秒
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// 更新当前播放状态，内容没有变化时不通知订阅者
+fn publish_now_playing(sender: &watch::Sender<NowPlaying>, now_playing: NowPlaying) {
+    sender.send_if_modified(|current| {
+        if *current == now_playing {
+            return false;
+        }
+        *current = now_playing;
+        true
+    });
+}
+
+// 编译进程序的所有歌词源
+#[allow(unused_variables)]
+fn get_all_providers(
+    matching: &MatchingConfig,
+    sources: &HashMap<String, api::SourceConfig>,
+    quiet: bool,
+) -> Vec<Box<dyn LyricsProviderTrait>> {
+    // 歌词源单独配置的相似度优先于全局配置
+    let min_title_similarity = |source: &str| {
+        sources
+            .get(source)
+            .and_then(|config| config.min_title_similarity)
+            .unwrap_or(matching.min_title_similarity)
+    };
+    #[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+    let timeout = |source: &str| {
+        Duration::from_secs(
+            sources
+                .get(source)
+                .and_then(|config| config.timeout_seconds)
+                .unwrap_or(api::REQWEST_TIMEOUT),
+        )
+    };
+    #[cfg(any(feature = "netease", feature = "qqmusic", feature = "lrclib"))]
+    let retries = |source: &str| api::RetryPolicy {
+        retries: sources
+            .get(source)
+            .and_then(|config| config.retries)
+            .unwrap_or(api::DEFAULT_RETRIES),
+        quiet,
+    };
+    vec![
+        #[cfg(feature = "netease")]
+        Box::new(api::netease::NeteaseLyricsProvider::new(
+            min_title_similarity("netease"),
+            timeout("netease"),
+            retries("netease"),
+        )),
+        #[cfg(feature = "qqmusic")]
+        Box::new(api::qq::QQMusicLyricsProvider::new(
+            min_title_similarity("qq"),
+            timeout("qq"),
+            retries("qq"),
+        )),
+        #[cfg(feature = "lrclib")]
+        Box::new(api::lrclib::LrclibLyricsProvider::new(
+            min_title_similarity("lrclib"),
+            timeout("lrclib"),
+            retries("lrclib"),
+        )),
+    ]
+}
+
+// 获取可用的歌词源，跳过 [sources.xxx] 中 enabled = false 的歌词源，离线模式下只保留不需要联网的歌词源
+fn get_enabled_providers(config: &Config) -> Vec<Box<dyn LyricsProviderTrait>> {
+    let mut providers = get_all_providers(&config.matching, &config.sources, config.quiet);
+    #[cfg(feature = "embedded")]
+    if config.embedded.enabled {
+        providers.push(Box::new(api::embedded::EmbeddedProvider));
+    }
+    if !config.command.command.is_empty() {
+        providers.push(Box::new(api::command::CommandProvider::new(
+            config.command.clone(),
+        )));
+    }
+    providers
+        .into_iter()
+        .filter(|provider| {
+            config
+                .sources
+                .get(&provider.get_source_name())
+                .and_then(|source| source.enabled)
+                .unwrap_or(true)
+        })
+        .filter(|provider| !config.offline || provider.capabilities().offline)
+        .collect()
+}
+
+// 翻译和注音输出到终端时调暗，重定向到文件或管道时原样输出
+fn print_translation(translation: &str) {
+    if std::io::stdout().is_terminal() {
+        println!("\x1b[2m{}\x1b[0m", translation);
+    } else {
+        println!("{}", translation);
+    }
+}
+
+// 以 waybar 的 JSON 格式输出一行，和上次输出相同时跳过，避免刷屏
+fn print_waybar_output(
+    last_output: &mut String,
+    text: &str,
+    title: &str,
+    artist: &str,
+    status: mpris::PlaybackStatus,
+) {
+    let class = match status {
+        mpris::PlaybackStatus::Playing => "playing",
+        mpris::PlaybackStatus::Paused => "paused",
+        mpris::PlaybackStatus::Stopped => "stopped",
+    };
+    let output = WaybarOutput {
+        text,
+        tooltip: format!("{} - {}", title, artist),
+        class,
+    };
+    write_waybar_output(last_output, &output);
+}
+
+// 没有播放器时输出空闲提示，否则 waybar 会一直显示上一句歌词
+fn print_waybar_idle(last_output: &mut String, text: &str) {
+    let output = WaybarOutput {
+        text,
+        tooltip: String::new(),
+        class: "idle",
+    };
+    write_waybar_output(last_output, &output);
+}
+
+fn write_waybar_output(last_output: &mut String, output: &WaybarOutput) {
+    let output = serde_json::to_string(output).unwrap();
+    if output != *last_output {
+        println!("{}", output);
+        let _ = std::io::stdout().flush();
+        *last_output = output;
+    }
+}
+
+// 纯文本歌词按行平均分布到整首歌，逐行显示，避免终端、waybar 等按行显示的地方一次输出整首歌
+// 不知道时长时只显示第一行
+fn spread_plain_lyrics(text: &str, length: u64) -> BTreeMap<u64, String> {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>();
+    if length == 0 {
+        return lines.first().map(|line| (0, line.to_string())).into_iter().collect();
+    }
+    let count = lines.len() as u64;
+    lines
+        .into_iter()
+        .enumerate()
+        .map(|(index, line)| (index as u64 * length / count, line.to_string()))
+        .collect()
+}
+
+// 推送的播放进度，只精确到秒，避免每次刷新都推送
+// 部分播放器在切歌前报告的位置会超过时长，已知时长时截断，避免进度超过 100%
+fn progress_position(position: u64, length: u64) -> u64 {
+    let position = if length > 0 { position.min(length) } else { position };
+    position / 1000 * 1000
 }
 
-fn display_lyrics(shared_data: Arc<Mutex<SharedData>>, refresh_interval: u64, sort_list: Vec<String>) {
-    let player_finder = PlayerFinder::new().unwrap();
+// 从歌词源获取歌词，best 模式请求所有歌词源，first 模式按 sort_list 顺序请求到第一个成功为止
+// 请求一次歌词源，fetch_lyrics 和覆盖配置按 id 获取歌词都通过这里调用
+// 每次请求最多等待 timeout，请求前更新心跳，避免依次请求多个歌词源时被看门狗误报卡死
+// 歌词源内部 panic（如上游 JSON 格式变化导致 unwrap 失败）和超时都按失败处理，不会卡住或中断整个流程
+fn call_provider(
+    runtime: &tokio::runtime::Runtime,
+    timeout: Duration,
+    heartbeat: &AtomicU64,
+    request: impl std::future::Future<Output = anyhow::Result<SearchLyricsInfo>>,
+) -> anyhow::Result<SearchLyricsInfo> {
+    heartbeat.store(now_millis(), Ordering::Relaxed);
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        runtime.block_on(async { tokio::time::timeout(timeout, request).await })
+    }))
+    .unwrap_or_else(|_| Ok(Err(anyhow::anyhow!("Provider panicked"))))
+    .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {} ms", timeout.as_millis())))
+}
+
+// first 模式开启 prefer_synced 时纯文本歌词不算成功，继续请求后面的歌词源，都没有时间轴时用第一个纯文本歌词兜底
+// 第二个返回值表示失败的歌词源是否都明确回答了没有歌词，有超时、网络错误等临时失败时为 false
+#[allow(clippy::too_many_arguments)]
+fn fetch_lyrics(
+    providers: &[Box<dyn LyricsProviderTrait>],
+    title: &str,
+    artist: &str,
+    album: &str,
+    length: u64,
+    url: &str,
+    sort_list: &[String],
+    strategy: FetchStrategy,
+    prefer_synced: bool,
+    timeout: Duration,
+    heartbeat: &AtomicU64,
+) -> (Vec<SearchLyricsInfo>, bool) {
+    let mut providers = providers.iter().collect::<Vec<_>>();
+    if strategy == FetchStrategy::First {
+        // 不在 sort_list 中的歌词源排在最后
+        providers.sort_by_key(|provider| {
+            let source = provider.get_source_name();
+            sort_list
+                .iter()
+                .position(|x| *x == source)
+                .unwrap_or(sort_list.len())
+        });
+    }
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut result = Vec::new();
+    let mut all_not_found = true;
+    for provider in providers {
+        let search_lyrics_info = call_provider(
+            &runtime,
+            timeout,
+            heartbeat,
+            provider.get_best_match_lyric(title, artist, album, length, url),
+        );
+        match search_lyrics_info {
+            Ok(search_lyrics_info) if strategy == FetchStrategy::First => {
+                if search_lyrics_info.is_synced() || !prefer_synced {
+                    result.clear();
+                    result.push(search_lyrics_info);
+                    break;
+                }
+                if result.is_empty() {
+                    result.push(search_lyrics_info);
+                }
+            }
+            Ok(search_lyrics_info) => result.push(search_lyrics_info),
+            Err(err) => {
+                all_not_found &= err.is::<api::NotFound>();
+                // 只打印错误信息，不打印堆栈
+                eprintln!("[{}]源获取歌词失败: {}", provider.get_source_name(), err);
+            }
+        }
+    }
+    (result, all_not_found)
+}
+
+// 开启 prefer_synced 时带时间轴的歌词优先，即使其他源的时长更接近，纯文本歌词只作为兜底
+// 之后依次比较标题相似度、按秒计的时长差、是否有翻译和精确的时长差，都相同时按照 sort_list 中的顺序排序
+fn sort_search_lyrics_info_list(
+    list: &mut [SearchLyricsInfo],
+    sort_list: &[String],
+    prefer_synced: bool,
+) {
+    list.sort_by(|a, b| {
+        if prefer_synced {
+            let synced_cmp = b.is_synced().cmp(&a.is_synced());
+            if synced_cmp != std::cmp::Ordering::Equal {
+                return synced_cmp;
+            }
+        }
+        let similarity_cmp = b.title_similarity.total_cmp(&a.title_similarity);
+        if similarity_cmp != std::cmp::Ordering::Equal {
+            return similarity_cmp;
+        }
+        // 一秒以内的时长差一般是同一个版本，这时有翻译的歌词优先
+        let delta_secs_cmp = (a.delta_abs / 1000).cmp(&(b.delta_abs / 1000));
+        if delta_secs_cmp != std::cmp::Ordering::Equal {
+            return delta_secs_cmp;
+        }
+        let translation_cmp = a.translations.is_empty().cmp(&b.translations.is_empty());
+        if translation_cmp != std::cmp::Ordering::Equal {
+            return translation_cmp;
+        }
+        let delta_abs_cmp = a.delta_abs.cmp(&b.delta_abs);
+        if delta_abs_cmp != std::cmp::Ordering::Equal {
+            return delta_abs_cmp;
+        }
+        let a_index = sort_list.iter().position(|x| *x == a.source);
+        let b_index = sort_list.iter().position(|x| *x == b.source);
+        if let (Some(a_index), Some(b_index)) = (a_index, b_index) {
+            return a_index.cmp(&b_index);
+        }
+        // Fallback to comparing by source if index not found
+        a.source.cmp(&b.source)
+    });
+}
+
+fn display_lyrics(
+    shared_data: &Mutex<SharedData>,
+    config: &Config,
+    now_playing: &watch::Sender<NowPlaying>,
+    heartbeat: &AtomicU64,
+    overrides: &overrides::Overrides,
+    cache: Option<&cache::LyricsCache>,
+) {
+    let refresh_interval = config.lyric_refresh_interval;
+    let provider_list = get_enabled_providers(config);
+    let player_finder = connect_player_finder(&config.polling);
     let mut current_player;
     let mut all_provider_failed = false;
-    let mut last_song_name = String::new();
+    let mut last_track = TrackKey::default();
+    let mut metadata_settle = MetadataSettle::default();
+    let mut position_estimate = PositionEstimate::default();
     loop {
+        heartbeat.store(now_millis(), Ordering::Relaxed);
+
         // 根据当前播放器的名字获取当前播放器
+        // 线程 panic 后会重启，这里忽略锁中毒
         let current_player_name = shared_data
             .lock()
-            .unwrap()
+            .unwrap_or_else(PoisonError::into_inner)
             .current_player_name
             .lock()
-            .unwrap()
+            .unwrap_or_else(PoisonError::into_inner)
             .clone();
 
+        let idle = NowPlaying {
+            line: config.display.idle_message.clone(),
+            ..Default::default()
+        };
+
+        let show_idle = |idle: NowPlaying| {
+            let shared_data = shared_data.lock().unwrap_or_else(PoisonError::into_inner);
+            let mut lyrics_info = shared_data
+                .lyrics_info
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+            match config.display.output_format {
+                OutputFormat::Line => {
+                    if !idle.line.is_empty() && idle.line != lyrics_info.last_printed_line {
+                        println!("{}", idle.line);
+                    }
+                }
+                OutputFormat::Json => print_waybar_idle(&mut lyrics_info.last_output, &idle.line),
+            }
+            lyrics_info.last_printed_line = idle.line.clone();
+            publish_now_playing(now_playing, idle);
+        };
+
         // 没有匹配到的播放器，不要调用finder，直接sleep
         if current_player_name.is_empty() {
+            show_idle(idle);
             thread::sleep(Duration::from_millis(refresh_interval));
             continue;
         }
@@ -78,6 +1030,7 @@ fn display_lyrics(shared_data: Arc<Mutex<SharedData>>, refresh_interval: u64, so
         // 尝试获取当前播放器，如果获取失败则继续循环
         let current_player_find = player_finder.find_by_name(current_player_name.as_str());
         if current_player_find.is_err() {
+            show_idle(idle);
             thread::sleep(Duration::from_millis(refresh_interval));
             continue;
         }
@@ -92,110 +1045,279 @@ fn display_lyrics(shared_data: Arc<Mutex<SharedData>>, refresh_interval: u64, so
                 continue;
             }
         };
-        let song_name = metadata.title().unwrap();
-        let artist = metadata.artists().unwrap().join(",");
-        let length = metadata.length().unwrap().as_millis();
-        let status = current_player.get_playback_status().unwrap();
-        let position = current_player.get_position().unwrap().as_millis();
+        let track = TrackKey::from_metadata(&metadata);
+        // 没有标题无法搜索歌词，等播放器补全 metadata
+        let Some(song_name) = metadata.title() else {
+            // 部分播放器停止后会清空 metadata，这时显示停止提示
+            if matches!(current_player.get_playback_status(), Ok(mpris::PlaybackStatus::Stopped)) {
+                show_idle(NowPlaying {
+                    line: config.display.stopped_message.clone(),
+                    ..Default::default()
+                });
+            }
+            thread::sleep(Duration::from_millis(refresh_interval));
+            continue;
+        };
+        let artist = metadata.artists().unwrap_or_default().join(",");
+        let keyword_artist = search_artist(&metadata, config.matching.artist_field);
+        let length = metadata.length().map(|d| d.as_millis()).unwrap_or(0);
+        let Ok(status) = current_player.get_playback_status() else {
+            thread::sleep(Duration::from_millis(refresh_interval));
+            continue;
+        };
+        // 获取不到播放进度时不跳过，切歌后再估算
+        let reported_position = current_player.get_position();
 
-        let shared_data = shared_data.lock().unwrap();
-        let mut lyrics_info = shared_data.lyrics_info.lock().unwrap();
+        if track != last_track && metadata_settle.should_wait(&track, now_millis()) {
+            thread::sleep(Duration::from_millis(refresh_interval));
+            continue;
+        }
+
+        let shared_data = shared_data.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut lyrics_info = shared_data
+            .lyrics_info
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
 
         // 歌曲未变，但是上次获取歌词所有源全部失败，继续循环，避免重复发请求
-        if song_name == last_song_name && all_provider_failed {
+        if track == last_track && all_provider_failed {
             thread::sleep(Duration::from_millis(refresh_interval));
             continue;
         }
 
         // 切歌时更新歌词信息
-        if song_name != last_song_name {
+        if track != last_track {
             // 强制记录一下，主要是为了下面所有歌词源都失败时的判断，避免重复发请求
-            last_song_name = song_name.to_string();
-
-            let netease_provider = api::netease::NeteaseLyricsProvider {};
-            let qq_provider = api::qq::QQMusicLyricsProvider {};
+            last_track = track;
+            lyrics_info.last_printed_line.clear();
+            position_estimate.reset();
+            publish_now_playing(
+                now_playing,
+                NowPlaying {
+                    title: song_name.to_string(),
+                    artist: artist.clone(),
+                    length: length as u64,
+                    ..Default::default()
+                },
+            );
 
-            let provider_list: Vec<&dyn LyricsProviderTrait> =
-                vec![&netease_provider, &qq_provider];
-
-            // 从所有源获取歌词，存入 vec
-            let search_lyrics_info_list = provider_list
-                .iter()
-                .map(|provider| {
-                    let search_lyrics_info =
-                        tokio::runtime::Runtime::new().unwrap().block_on(provider
-                            .get_best_match_lyric(&format!("{} {}", artist, song_name), length as u64));
-                    match search_lyrics_info {
-                        Ok(search_lyrics_info) => Some(search_lyrics_info),
-                        Err(err) => {
-                            // 只打印错误信息，不打印堆栈
-                            println!("[{}]源获取歌词失败: {}", provider.get_source_name(), err.to_string());
-                            None
-                        }
-                    }
-                })
-                .filter(|x| x.is_some())
-                .collect::<Vec<_>>();
+            // 先查缓存，未命中再请求歌词源，结果（包括没有找到歌词）写回缓存
+            let album = metadata.album_name().unwrap_or_default();
+            let cache_key = cache::cache_key(song_name, &artist, album, length as u64);
+            // 覆盖配置优先于缓存，结果也不写入缓存，修改覆盖配置后马上生效
+            let overridden = overrides.resolve(
+                &artist,
+                song_name,
+                &provider_list,
+                Duration::from_millis(config.provider_timeout),
+                heartbeat,
+            );
+            let cached = if overridden.is_some() {
+                None
+            } else {
+                cache.and_then(|cache| cache.load(&cache_key))
+            };
+            let from_cache = cached.is_some();
+            let search_lyrics_info = overridden.map(Some).or(cached).unwrap_or_else(|| {
+                let (mut search_lyrics_info_list, all_not_found) = fetch_lyrics(
+                    &provider_list,
+                    song_name,
+                    &keyword_artist,
+                    album,
+                    length as u64,
+                    metadata.url().unwrap_or_default(),
+                    &config.sort_list,
+                    config.matching.strategy,
+                    config.prefer_synced,
+                    Duration::from_millis(config.provider_timeout),
+                    heartbeat,
+                );
+                sort_search_lyrics_info_list(
+                    &mut search_lyrics_info_list,
+                    &config.sort_list,
+                    config.prefer_synced,
+                );
+                let best = (!search_lyrics_info_list.is_empty())
+                    .then(|| search_lyrics_info_list.swap_remove(0));
+                // 临时失败或离线模式跳过了网络歌词源时不缓存“没有歌词”，下次启动还会重新请求
+                let cache = cache.filter(|_| best.is_some() || (all_not_found && !config.offline));
+                if let Some(Err(err)) = cache.map(|cache| cache.store(&cache_key, best.as_ref())) {
+                    eprintln!("写入歌词缓存失败: {}", err);
+                }
+                best
+            });
 
             // 如果所有歌词源都失败，打印错误信息，继续循环
-            all_provider_failed = search_lyrics_info_list.is_empty();
-            if all_provider_failed {
-                println!("所有歌词源都失败");
+            all_provider_failed = search_lyrics_info.is_none();
+            let Some(mut search_lyrics_info) = search_lyrics_info else {
+                if from_cache {
+                    if !config.quiet {
+                        eprintln!("没有找到歌词（缓存）");
+                    }
+                } else {
+                    eprintln!("所有歌词源都失败");
+                }
+                // 清掉上一首歌的歌词
+                if config.display.output_format == OutputFormat::Json {
+                    print_waybar_output(&mut lyrics_info.last_output, "", song_name, &artist, status);
+                }
                 thread::sleep(Duration::from_millis(refresh_interval));
                 continue;
+            };
+            if config.lyrics.collapse_repeats {
+                search_lyrics_info.collapse_repeated_lines();
+            } else if config.lyrics.dedup_adjacent {
+                search_lyrics_info.dedup_adjacent_lines(config.lyrics.dedup_window);
+            }
+            if config.lyrics.skip_credit_lines {
+                search_lyrics_info.skip_credit_lines(song_name, &artist);
+            }
+            let instrumental = search_lyrics_info.is_instrumental(&config.lyrics.instrumental_markers);
+            if instrumental {
+                search_lyrics_info.lyrics =
+                    BTreeMap::from([(0, config.display.instrumental_message.clone())]);
+                search_lyrics_info.translations.clear();
             }
-
-            // 按照 delta_abs 从小到大排序，delta_abs 相同的情况下，按照 sort_list 中的顺序排序
-            let mut sorted_lyrics_info_list = search_lyrics_info_list;
-            sorted_lyrics_info_list.sort_by(|a, b| {
-                let delta_abs_cmp = a.as_ref().unwrap().delta_abs.cmp(&b.as_ref().unwrap().delta_abs);
-                if delta_abs_cmp != std::cmp::Ordering::Equal {
-                    return delta_abs_cmp;
-                }
-                let a_index = sort_list.iter().position(|x| *x == a.as_ref().unwrap().source);
-                let b_index = sort_list.iter().position(|x| *x == b.as_ref().unwrap().source);
-                if let (Some(a_index), Some(b_index)) = (a_index, b_index) {
-                    return a_index.cmp(&b_index);
-                }
-                // Fallback to comparing by source if index not found
-                a.as_ref().unwrap().source.cmp(&b.as_ref().unwrap().source)
-            });
-
-            let search_lyrics_info = sorted_lyrics_info_list.first();
 
             lyrics_info.title = song_name.to_string();
             lyrics_info.artist = artist.to_string();
             lyrics_info.length = length as u64;
-            lyrics_info.lyrics = search_lyrics_info.unwrap().as_ref().unwrap().lyrics.clone();
-            println!("{} - {}", artist, song_name);
+            if search_lyrics_info.synced {
+                lyrics_info.lyrics = search_lyrics_info.lyrics;
+                lyrics_info.plain_lyrics.clear();
+            } else {
+                lyrics_info.plain_lyrics = search_lyrics_info.lyrics.into_values().collect::<Vec<_>>().join("\n");
+                lyrics_info.lyrics = spread_plain_lyrics(&lyrics_info.plain_lyrics, length as u64);
+            }
+            // 纯音乐提示是配置的文字，不注音
+            lyrics_info.transliterations = if instrumental {
+                BTreeMap::new()
+            } else {
+                lyrics_info
+                    .lyrics
+                    .iter()
+                    .filter_map(|(timestamp, lyric)| {
+                        transliterate(lyric, config.display.transliteration).map(|text| (*timestamp, text))
+                    })
+                    .collect()
+            };
+            lyrics_info.translations = if config.display.show_translation {
+                search_lyrics_info.translations
+            } else {
+                BTreeMap::new()
+            };
+            lyrics_info.source = search_lyrics_info.source;
+            if !config.quiet && config.display.output_format == OutputFormat::Line {
+                println!("{} - {}", artist, song_name);
+            }
         }
 
-        // 未播放时不显示歌词
+        let position = match reported_position {
+            Ok(position) => {
+                position_estimate.sync(position.as_millis() as u64, now_millis());
+                position.as_millis()
+            }
+            Err(_) => position_estimate
+                .advance(status == mpris::PlaybackStatus::Playing, now_millis())
+                as u128,
+        };
+
+        // 未播放时不显示歌词，停止播放时显示提示，暂停时保留当前歌词
+        if status == mpris::PlaybackStatus::Stopped {
+            if lyrics_info.last_printed_line != config.display.stopped_message {
+                if config.display.output_format == OutputFormat::Line {
+                    println!("{}", config.display.stopped_message);
+                }
+                // 记为已打印，恢复播放后会重新打印当前歌词
+                lyrics_info.last_printed_line = config.display.stopped_message.clone();
+            }
+            publish_now_playing(
+                now_playing,
+                NowPlaying {
+                    title: lyrics_info.title.clone(),
+                    artist: lyrics_info.artist.clone(),
+                    line: config.display.stopped_message.clone(),
+                    length: lyrics_info.length,
+                    ..Default::default()
+                },
+            );
+        }
         if status != mpris::PlaybackStatus::Playing {
+            if config.display.output_format == OutputFormat::Json {
+                let lyrics_info = &mut *lyrics_info;
+                print_waybar_output(
+                    &mut lyrics_info.last_output,
+                    &lyrics_info.last_printed_line,
+                    &lyrics_info.title,
+                    &lyrics_info.artist,
+                    status,
+                );
+            }
             thread::sleep(Duration::from_millis(refresh_interval));
             continue;
         }
 
-        // 获取当前播放时间对应的歌词
-        let lyrics = lyrics_info.lyrics.clone();
+        // 获取当前播放时间对应的歌词，直接借用，避免每次刷新都复制整份歌词
+        let lyrics_info = &mut *lyrics_info;
 
         // 查找最近的歌词，歌词时间小于等于当前播放时间
-        let current_lyric = lyrics
-            .range(..=position as u64)
-            .next_back()
-            .map(|(_, &ref value)| value);
-
-        match current_lyric {
-            Some(lyric) => {
-                // 打印歌词，如果歌词没有变化则不打印，防止刷屏
-                if lyric != &lyrics_info.last_printed_line {
+        let current_lyric = lyrics_info.lyrics.range(..=position as u64).next_back();
+        let next_line = lyrics_info
+            .lyrics
+            .range(position as u64 + 1..)
+            .next()
+            .map(|(_, lyric)| lyric.clone())
+            .unwrap_or_default();
+        // 翻译和原歌词时间相同才显示
+        let translation = current_lyric
+            .and_then(|(timestamp, _)| lyrics_info.translations.get(timestamp))
+            .cloned()
+            .unwrap_or_default();
+        let transliteration = current_lyric
+            .and_then(|(timestamp, _)| lyrics_info.transliterations.get(timestamp))
+            .cloned()
+            .unwrap_or_default();
+
+        // 打印歌词，如果歌词没有变化则不打印，防止刷屏
+        if let Some((_, lyric)) = current_lyric {
+            if lyric != &lyrics_info.last_printed_line {
+                if config.display.output_format == OutputFormat::Line {
                     println!("{}", lyric);
-                    lyrics_info.last_printed_line = lyric.clone();
+                    if !transliteration.is_empty() {
+                        print_translation(&transliteration);
+                    }
+                    if !translation.is_empty() {
+                        print_translation(&translation);
+                    }
                 }
+                lyrics_info.last_printed_line = lyric.clone();
             }
-            _ => {}
+        }
+        if config.display.output_format == OutputFormat::Json {
+            print_waybar_output(
+                &mut lyrics_info.last_output,
+                &lyrics_info.last_printed_line,
+                &lyrics_info.title,
+                &lyrics_info.artist,
+                status,
+            );
         }
 
+        publish_now_playing(
+            now_playing,
+            NowPlaying {
+                title: lyrics_info.title.clone(),
+                artist: lyrics_info.artist.clone(),
+                line: lyrics_info.last_printed_line.clone(),
+                translation,
+                transliteration,
+                next_line,
+                plain_lyrics: lyrics_info.plain_lyrics.clone(),
+                source: lyrics_info.source.clone(),
+                position: progress_position(position as u64, lyrics_info.length),
+                length: lyrics_info.length,
+            },
+        );
 
         // 休眠一段时间
         thread::sleep(Duration::from_millis(refresh_interval));
@@ -203,16 +1325,76 @@ fn display_lyrics(shared_data: Arc<Mutex<SharedData>>, refresh_interval: u64, so
 }
 
 fn main() {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+
     let pkg_name = env!("CARGO_PKG_NAME");
+    if args.help {
+        print!("{}", USAGE);
+        return;
+    }
+    if args.version {
+        println!("{} {}", pkg_name, env!("CARGO_PKG_VERSION"));
+        return;
+    }
     let xdg_dir = xdg::BaseDirectories::with_prefix(pkg_name).unwrap();
 
+    if args.build_info {
+        print_build_info(&xdg_dir);
+        return;
+    }
+    if args.list_players {
+        if let Err(err) = list_players() {
+            eprintln!("获取播放器列表失败: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // 读取配置文件
     let config_path = xdg_dir
         .find_config_file("config.toml")
         .expect("未找到配置文件，正在退出...");
-    let config: Config = toml::from_str(&fs::read_to_string(config_path).unwrap()).unwrap();
+    if args.migrate_config {
+        let content = fs::read_to_string(&config_path).unwrap();
+        match migrate_config(&content) {
+            Ok(migrated) if migrated == content => println!("配置文件已是最新"),
+            Ok(migrated) => {
+                // 先备份原文件，避免写坏后丢失配置
+                fs::write(config_path.with_extension("toml.bak"), &content).unwrap();
+                fs::write(&config_path, migrated).unwrap();
+                println!("已更新配置文件: {}", config_path.display());
+            }
+            Err(err) => {
+                eprintln!("配置文件迁移失败: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let mut config: Config = toml::from_str(&fs::read_to_string(config_path).unwrap()).unwrap();
+    // 命令行参数优先于配置文件
+    config.quiet |= args.quiet;
+    config.offline |= args.offline;
+    if let Some(output_format) = args.output_format {
+        config.display.output_format = output_format;
+    }
 
-    let player_finder = PlayerFinder::new().unwrap();
+    if config.offline {
+        if !config.quiet {
+            eprintln!("离线模式已开启，不会请求网络歌词源");
+        }
+        if get_enabled_providers(&config).is_empty() {
+            eprintln!("没有可用的离线歌词源");
+        }
+    }
+
+    let player_finder = connect_player_finder(&config.polling);
 
     // 创建一个线程用于显示歌词
     let shared_data = Arc::new(Mutex::new(SharedData {
@@ -222,32 +1404,621 @@ fn main() {
             artist: String::new(),
             length: 0,
             lyrics: BTreeMap::new(),
+            translations: BTreeMap::new(),
+            transliterations: BTreeMap::new(),
+            plain_lyrics: String::new(),
+            source: String::new(),
             last_printed_line: String::new(),
+            last_output: String::new(),
         })),
     }));
 
+    // 网页歌词、D-Bus 服务、IPC socket 和歌词显示线程通过 watch channel 共享当前播放状态
+    // 未启用 web、dbus 和 ipc feature 时 receiver 不会被使用
+    #[allow(unused_variables)]
+    let (now_playing_tx, now_playing_rx) = watch::channel(NowPlaying::default());
+    #[cfg(feature = "dbus")]
+    if config.dbus.publish {
+        let now_playing_rx = now_playing_rx.clone();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            if let Err(err) = runtime.block_on(dbus::serve(now_playing_rx)) {
+                eprintln!("D-Bus 歌词服务启动失败: {}", err);
+            }
+        });
+    }
+    #[cfg(feature = "ipc")]
+    if !config.ipc.socket_path.is_empty() {
+        let socket_path = config.ipc.socket_path.clone();
+        let now_playing_rx = now_playing_rx.clone();
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            if let Err(err) = runtime.block_on(ipc::serve(&socket_path, now_playing_rx)) {
+                eprintln!("IPC socket 启动失败: {}", err);
+            }
+        });
+    }
+    #[cfg(feature = "web")]
+    if config.web.enabled {
+        let addr = config.web.addr.clone();
+        if !config.quiet {
+            eprintln!("网页歌词: http://{}", addr);
+        }
+        thread::spawn(move || {
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            if let Err(err) = runtime.block_on(web::serve(&addr, now_playing_rx)) {
+                eprintln!("网页歌词服务启动失败: {}", err);
+            }
+        });
+    }
+
+    // 歌词显示线程 panic 时记录并重启，避免主线程还在运行但歌词不再更新
+    let heartbeat = Arc::new(AtomicU64::new(now_millis()));
     let shared_data_clone = Arc::clone(&shared_data);
+    let heartbeat_clone = Arc::clone(&heartbeat);
+    let config_clone = config.clone();
+    let lyrics_overrides = if config.overrides.is_empty() {
+        overrides::Overrides::default()
+    } else {
+        let path = xdg_dir.get_config_file(&config.overrides);
+        overrides::Overrides::load(&path).unwrap_or_else(|err| {
+            eprintln!("覆盖配置 {} 读取失败，不使用覆盖配置: {}", path.display(), err);
+            overrides::Overrides::default()
+        })
+    };
+    let lyrics_cache = if config.cache.enabled {
+        xdg_dir
+            .create_cache_directory("lyrics")
+            .and_then(|dir| cache::LyricsCache::new(dir, &config.cache))
+            .map_err(|err| eprintln!("歌词缓存目录创建失败，不使用缓存: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    thread::spawn(move || loop {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            display_lyrics(
+                &shared_data_clone,
+                &config_clone,
+                &now_playing_tx,
+                &heartbeat_clone,
+                &lyrics_overrides,
+                lyrics_cache.as_ref(),
+            )
+        }));
+        if result.is_err() {
+            eprintln!("歌词显示线程异常退出，正在重启");
+        }
+        thread::sleep(Duration::from_secs(1));
+    });
+
+    // 单独检查歌词显示线程的心跳，显示线程卡住时可能一直持有共享数据的锁，不能放在主线程里
+    // 单个歌词源最多请求 provider_timeout，超时时间设得更长时相应放宽
+    let heartbeat_timeout = DISPLAY_HEARTBEAT_TIMEOUT.max(config.provider_timeout * 2);
     thread::spawn(move || {
-        display_lyrics(shared_data_clone, config.lyric_refresh_interval, config.sort_list);
+        let mut stalled = false;
+        loop {
+            thread::sleep(Duration::from_millis(DISPLAY_HEARTBEAT_TIMEOUT / 3));
+            let elapsed = now_millis().saturating_sub(heartbeat.load(Ordering::Relaxed));
+            // 只在状态变化时提示一次
+            if elapsed > heartbeat_timeout && !stalled {
+                eprintln!("歌词显示线程已 {} 秒无响应", elapsed / 1000);
+            } else if elapsed <= heartbeat_timeout && stalled {
+                eprintln!("歌词显示线程已恢复");
+            }
+            stalled = elapsed > heartbeat_timeout;
+        }
     });
 
     // 主线程用于更新当前播放器
+    // 有播放器时（包括暂停）快速轮询，没有播放器或 D-Bus 请求失败时逐渐放慢，减少空闲时的唤醒
+    let mut idle_interval: Option<u64> = None;
     loop {
         // 获取当前播放器
-        let current_player = find_current_player(&player_finder, &config.white_list);
-        match current_player {
+        let current_player = find_current_player(&player_finder, &config.white_list, &config.player_aliases);
+        let interval = match current_player {
             Ok(current_player) => {
                 // 更新当前播放器
-                shared_data.lock().unwrap().current_player_name =
-                    Arc::new(Mutex::new(current_player.identity().to_string()));
+                shared_data
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .current_player_name = Arc::new(Mutex::new(current_player.identity().to_string()));
+                if idle_interval.take().is_some() && !config.quiet {
+                    eprintln!("检测到播放器，切换到快速轮询");
+                }
+                config.player_refresh_interval
             }
-            Err(_) => {
+            Err(err) => {
                 // 重置当前播放器名称
-                shared_data.lock().unwrap().current_player_name = Arc::new(Mutex::new(String::new()));
+                shared_data
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .current_player_name = Arc::new(Mutex::new(String::new()));
+                if let mpris::FindingError::DBusError(err) = err {
+                    eprintln!("D-Bus 请求失败: {}", err);
+                }
+                let interval = match idle_interval {
+                    Some(interval) => next_backoff(interval, config.polling.idle_max_interval),
+                    None => {
+                        if !config.quiet {
+                            eprintln!("没有白名单中的播放器，切换到慢速轮询");
+                        }
+                        config.polling.idle_min_interval
+                    }
+                };
+                idle_interval = Some(interval);
+                interval
             }
-        }
+        };
 
         // 休眠一段时间
-        thread::sleep(Duration::from_millis(config.player_refresh_interval));
+        thread::sleep(Duration::from_millis(interval));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct MockProvider {
+        source: &'static str,
+        lyrics: Option<BTreeMap<u64, String>>,
+        synced: bool,
+        delta_abs: i64,
+    }
+
+    #[async_trait]
+    impl LyricsProviderTrait for MockProvider {
+        fn get_source_name(&self) -> String {
+            String::from(self.source)
+        }
+        fn capabilities(&self) -> api::ProviderCapabilities {
+            api::ProviderCapabilities::default()
+        }
+        async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, _url: &str) -> anyhow::Result<SearchLyricsInfo> {
+            let lyrics = self
+                .lyrics
+                .clone()
+                .ok_or_else(|| api::NotFound(String::from("No songs found")))?;
+            Ok(SearchLyricsInfo {
+                source: self.get_source_name(),
+                lyrics,
+                translations: BTreeMap::new(),
+                synced: self.synced,
+                delta_abs: self.delta_abs,
+                title_similarity: 1.0,
+            })
+        }
+    }
+
+    struct PanickingProvider;
+
+    #[async_trait]
+    impl LyricsProviderTrait for PanickingProvider {
+        fn get_source_name(&self) -> String {
+            String::from("panicking")
+        }
+        fn capabilities(&self) -> api::ProviderCapabilities {
+            api::ProviderCapabilities::default()
+        }
+        async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, _url: &str) -> anyhow::Result<SearchLyricsInfo> {
+            panic!("unexpected upstream json");
+        }
+    }
+
+    struct SlowProvider;
+
+    #[async_trait]
+    impl LyricsProviderTrait for SlowProvider {
+        fn get_source_name(&self) -> String {
+            String::from("slow")
+        }
+        fn capabilities(&self) -> api::ProviderCapabilities {
+            api::ProviderCapabilities::default()
+        }
+        async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, _url: &str) -> anyhow::Result<SearchLyricsInfo> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    fn mock_providers() -> Vec<Box<dyn LyricsProviderTrait>> {
+        let plain = BTreeMap::from([(0, String::from("第一句\n第二句"))]);
+        let synced = BTreeMap::from([(0, String::from("第一句"))]);
+        vec![
+            Box::new(MockProvider { source: "broken", lyrics: None, synced: true, delta_abs: 0 }),
+            Box::new(MockProvider { source: "plain", lyrics: Some(plain), synced: false, delta_abs: 0 }),
+            Box::new(MockProvider { source: "synced", lyrics: Some(synced), synced: true, delta_abs: 1000 }),
+        ]
+    }
+
+    #[test]
+    fn test_track_key_same_tags_different_length() {
+        // 和 display_lyrics 一样从播放器的 metadata 生成曲目标识
+        let track = |entries: &[(&str, mpris::MetadataValue)]| {
+            let mut metadata = HashMap::from([(
+                String::from("xesam:title"),
+                mpris::MetadataValue::String(String::from("未命名录音")),
+            )]);
+            metadata.extend(entries.iter().map(|(key, value)| (key.to_string(), value.clone())));
+            TrackKey::from_metadata(&mpris::Metadata::from(metadata))
+        };
+        let length = |millis: i64| ("mpris:length", mpris::MetadataValue::I64(millis * 1000));
+        let url = |url: &str| ("xesam:url", mpris::MetadataValue::String(url.to_string()));
+
+        // 标签相同，时长或文件不同时视为切歌
+        assert_ne!(track(&[length(61000)]), track(&[length(95000)]));
+        assert_ne!(
+            track(&[length(61000), url("file:///tmp/1.ogg")]),
+            track(&[length(61000), url("file:///tmp/2.ogg")])
+        );
+        assert_eq!(track(&[length(61000)]), track(&[length(61000)]));
+
+        // NoTrack 不算曲目 id
+        let no_track = (
+            "mpris:trackid",
+            mpris::MetadataValue::String(String::from("/org/mpris/MediaPlayer2/TrackList/NoTrack")),
+        );
+        assert_eq!(track(&[length(61000), no_track]), track(&[length(61000)]));
+    }
+
+    #[test]
+    fn test_is_player_match() {
+        let aliases = HashMap::from([(
+            String::from("mpd"),
+            vec![String::from("Music Player Daemon")],
+        )]);
+        // 别名
+        assert!(is_player_match("mpd", "Music Player Daemon", "org.mpris.MediaPlayer2.mpDris2", &aliases));
+        assert!(is_player_match("Music Player Daemon", "mpd", "org.mpris.MediaPlayer2.mpd", &aliases));
+        // 规范化后完全相同，Identity 或总线名任一匹配即可
+        assert!(is_player_match("Spotify", "Spotify", "org.mpris.MediaPlayer2.spotify", &aliases));
+        assert!(is_player_match(
+            "lx-music-desktop",
+            "LX Music",
+            "org.mpris.MediaPlayer2.lx_music_desktop",
+            &aliases
+        ));
+        // 浏览器的总线名带实例后缀
+        assert!(is_player_match(
+            "firefox",
+            "Mozilla Firefox",
+            "org.mpris.MediaPlayer2.firefox.instance_1_92",
+            &aliases
+        ));
+        assert!(is_player_match("chromium", "Chromium", "org.mpris.MediaPlayer2.chromium.instance12345", &aliases));
+        // 只是包含关系不匹配
+        assert!(!is_player_match("Spotify", "spotifyd", "org.mpris.MediaPlayer2.spotifyd", &aliases));
+        assert!(!is_player_match("mpd", "mpv", "org.mpris.MediaPlayer2.mpv", &aliases));
+    }
+
+    #[test]
+    fn test_format_table() {
+        let rows = [
+            [String::from("名称"), String::from("总线名")],
+            [String::from("mpd"), String::from("org.mpris.MediaPlayer2.mpd")],
+        ];
+        // 中文标题占两列，第二列都从第 6 列开始
+        assert_eq!(format_table(&rows), "名称  总线名\nmpd   org.mpris.MediaPlayer2.mpd\n");
+    }
+
+    #[test]
+    fn test_position_estimate() {
+        let mut estimate = PositionEstimate::default();
+        assert_eq!(estimate.advance(true, 1000), 0);
+        assert_eq!(estimate.advance(true, 1500), 500);
+        // 暂停期间不计入进度
+        assert_eq!(estimate.advance(false, 5000), 500);
+        assert_eq!(estimate.advance(true, 5200), 700);
+        estimate.sync(30000, 6000);
+        assert_eq!(estimate.advance(true, 6100), 30100);
+        estimate.reset();
+        assert_eq!(estimate.advance(true, 7000), 0);
+    }
+
+    #[test]
+    fn test_metadata_settle_waits_for_refined_track() {
+        let sparse = TrackKey {
+            title: String::from("玫瑰少年"),
+            ..Default::default()
+        };
+        let rich = TrackKey {
+            artist: String::from("蔡依林"),
+            length: 216000,
+            ..sparse.clone()
+        };
+        let mut settle = MetadataSettle::default();
+        assert!(settle.should_wait(&sparse, 0));
+        assert!(settle.should_wait(&sparse, 500));
+        // 补全后立即搜索
+        assert!(!settle.should_wait(&rich, 600));
+
+        // 一直不补全时超时后照常搜索
+        let mut settle = MetadataSettle::default();
+        assert!(settle.should_wait(&sparse, 0));
+        assert!(!settle.should_wait(&sparse, METADATA_SETTLE_TIMEOUT));
+    }
+
+    #[test]
+    fn test_search_artist() {
+        let artists = |names: &[&str]| {
+            mpris::MetadataValue::Array(
+                names
+                    .iter()
+                    .map(|name| mpris::MetadataValue::String(name.to_string()))
+                    .collect(),
+            )
+        };
+        let metadata = mpris::Metadata::from(HashMap::from([
+            (String::from("xesam:artist"), artists(&["周杰伦", "费玉清"])),
+            (String::from("xesam:albumArtist"), artists(&["周杰伦"])),
+        ]));
+        assert_eq!(search_artist(&metadata, ArtistField::All), "周杰伦,费玉清");
+        assert_eq!(search_artist(&metadata, ArtistField::First), "周杰伦");
+        assert_eq!(search_artist(&metadata, ArtistField::AlbumArtist), "周杰伦");
+
+        // 没有专辑歌手时使用所有歌手
+        let metadata = mpris::Metadata::from(HashMap::from([(
+            String::from("xesam:artist"),
+            artists(&["周杰伦", "费玉清"]),
+        )]));
+        assert_eq!(search_artist(&metadata, ArtistField::AlbumArtist), "周杰伦,费玉清");
+        assert_eq!(search_artist(&mpris::Metadata::from(HashMap::new()), ArtistField::First), "");
+    }
+
+    #[test]
+    fn test_partial_config_uses_defaults() {
+        let config: Config = toml::from_str("sort_list = [\"qq\"]\n[lyrics]\ndedup_adjacent = true").unwrap();
+        assert_eq!(config.sort_list, vec![String::from("qq")]);
+        assert!(config.lyrics.dedup_adjacent);
+        // 其余配置项使用默认值，而不是整个配置被丢弃
+        assert_eq!(config.player_refresh_interval, 3000);
+        assert_eq!(config.lyric_refresh_interval, 50);
+        assert_eq!(config.white_list, Config::default().white_list);
+        assert!(config.prefer_synced);
+        assert_eq!(config.lyrics.dedup_window, 200);
+    }
+
+    #[test]
+    fn test_disabled_source_is_skipped() {
+        let config: Config = toml::from_str("[sources.lrclib]\nenabled = false").unwrap();
+        let sources = get_enabled_providers(&config)
+            .iter()
+            .map(|provider| provider.get_source_name())
+            .collect::<Vec<_>>();
+        assert!(!sources.contains(&String::from("lrclib")));
+        #[cfg(feature = "netease")]
+        assert!(sources.contains(&String::from("netease")));
+    }
+
+    #[test]
+    fn test_migrate_config_keeps_values_and_comments() {
+        let content = r#"# 播放器刷新间隔
+player_refresh_interval = 1000
+lyric_refresh_interval = 50
+white_list = ["mpd"]
+sort_list = ["qq"]
+
+[lyrics]
+dedup_adjacent = true
+"#;
+        let migrated = migrate_config(content).unwrap();
+        assert!(migrated.starts_with("# 播放器刷新间隔\nplayer_refresh_interval = 1000\n"));
+        assert!(migrated.contains("dedup_adjacent = true\ndedup_window = 200\n"));
+        assert!(migrated.contains("[matching]"));
+        // 空的表没有意义，不写入
+        assert!(!migrated.contains("[sources]"));
+
+        let config: Config = toml::from_str(&migrated).unwrap();
+        assert_eq!(config.player_refresh_interval, 1000);
+        assert!(config.lyrics.dedup_adjacent);
+        // 再次迁移不应有变化
+        assert_eq!(migrate_config(&migrated).unwrap(), migrated);
+    }
+
+    #[test]
+    fn test_progress_position_clamped_to_length() {
+        assert_eq!(progress_position(61500, 216000), 61000);
+        assert_eq!(progress_position(216000, 216000), 216000);
+        assert_eq!(progress_position(218500, 216000), 216000);
+        // 时长未知时不截断
+        assert_eq!(progress_position(218500, 0), 218000);
+    }
+
+    #[test]
+    fn test_next_backoff() {
+        assert_eq!(next_backoff(5000, 30000), 10000);
+        assert_eq!(next_backoff(20000, 30000), 30000);
+        assert_eq!(next_backoff(u64::MAX, u64::MAX), u64::MAX);
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let args = Args::parse(vec![String::from("--quiet"), String::from("--offline")]).unwrap();
+        assert!(args.quiet);
+        assert!(args.offline);
+        assert!(Args::parse(vec![String::from("--build-info")]).unwrap().build_info);
+        assert!(Args::parse(vec![String::from("--list-players")]).unwrap().list_players);
+        assert!(Args::parse(vec![String::from("--migrate-config")]).unwrap().migrate_config);
+        assert_eq!(Args::parse(Vec::new()).unwrap(), Args::default());
+        assert!(Args::parse(vec![String::from("--debug")]).is_err());
+        assert!(Args::parse(vec![String::from("-h")]).unwrap().help);
+        assert!(Args::parse(vec![String::from("--help")]).unwrap().help);
+        assert!(Args::parse(vec![String::from("-V")]).unwrap().version);
+        assert!(Args::parse(vec![String::from("--version")]).unwrap().version);
+
+        let args = Args::parse(vec![String::from("--output-format"), String::from("json")]).unwrap();
+        assert_eq!(args.output_format, Some(OutputFormat::Json));
+        assert!(Args::parse(vec![String::from("--output-format")]).is_err());
+        assert!(Args::parse(vec![String::from("--output-format"), String::from("xml")]).is_err());
+    }
+
+    #[test]
+    fn test_print_waybar_output() {
+        let mut last_output = String::new();
+        print_waybar_output(&mut last_output, "歌词", "玫瑰少年", "蔡依林", mpris::PlaybackStatus::Playing);
+        assert_eq!(
+            last_output,
+            r#"{"text":"歌词","tooltip":"玫瑰少年 - 蔡依林","class":"playing"}"#
+        );
+        print_waybar_output(&mut last_output, "歌词", "玫瑰少年", "蔡依林", mpris::PlaybackStatus::Paused);
+        assert!(last_output.ends_with(r#""class":"paused"}"#));
+
+        print_waybar_idle(&mut last_output, "暂无播放");
+        assert_eq!(last_output, r#"{"text":"暂无播放","tooltip":"","class":"idle"}"#);
+    }
+
+    #[test]
+    fn test_fetch_lyrics_strategy() {
+        let sort_list = vec![String::from("synced"), String::from("plain")];
+        let providers = mock_providers();
+
+        // first 按 sort_list 顺序请求，拿到第一个结果就停止
+        let (result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::First, true, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "synced");
+
+        // best 请求所有歌词源，排序后带时间轴的歌词优先
+        let (mut result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::Best, true, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 2);
+        sort_search_lyrics_info_list(&mut result, &sort_list, true);
+        assert_eq!(result[0].source, "synced");
+    }
+
+    #[test]
+    fn test_fetch_lyrics_first_prefers_synced() {
+        let sort_list = vec![String::from("plain"), String::from("synced")];
+        let providers = mock_providers();
+
+        // 纯文本歌词不算成功，继续请求后面带时间轴的歌词源
+        let (result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::First, true, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "synced");
+
+        // 关闭 prefer_synced 时仍然在第一个结果处停止
+        let (result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::First, false, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "plain");
+
+        // 没有带时间轴的歌词时用纯文本歌词兜底
+        let plain_only = providers.into_iter().filter(|provider| provider.get_source_name() != "synced").collect::<Vec<_>>();
+        let (result, _) = fetch_lyrics(&plain_only, "", "", "", 0, "", &sort_list, FetchStrategy::First, true, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "plain");
+    }
+
+    #[test]
+    fn test_fetch_lyrics_survives_panicking_provider() {
+        let mut providers: Vec<Box<dyn LyricsProviderTrait>> = vec![Box::new(PanickingProvider)];
+        providers.extend(mock_providers());
+        let sort_list = vec![String::from("panicking"), String::from("synced")];
+        let (result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::First, true, Duration::from_secs(1), &AtomicU64::default());
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "synced");
+    }
+
+    #[test]
+    fn test_fetch_lyrics_skips_slow_provider() {
+        let mut providers: Vec<Box<dyn LyricsProviderTrait>> = vec![Box::new(SlowProvider)];
+        providers.extend(mock_providers());
+        let sort_list = vec![String::from("slow"), String::from("synced")];
+        let heartbeat = AtomicU64::default();
+        let (result, _) = fetch_lyrics(&providers, "", "", "", 0, "", &sort_list, FetchStrategy::First, true, Duration::from_millis(50), &heartbeat);
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].source, "synced");
+        // 请求每个歌词源前都更新心跳
+        assert!(now_millis() - heartbeat.load(Ordering::Relaxed) < 1000);
+    }
+
+    #[test]
+    fn test_fetch_lyrics_reports_not_found() {
+        let not_found: Vec<Box<dyn LyricsProviderTrait>> =
+            vec![Box::new(MockProvider { source: "broken", lyrics: None, synced: true, delta_abs: 0 })];
+        let (result, all_not_found) = fetch_lyrics(&not_found, "", "", "", 0, "", &[], FetchStrategy::Best, true, Duration::from_secs(1), &AtomicU64::default());
+        assert!(result.is_empty());
+        assert!(all_not_found);
+
+        // 超时不算没有歌词，不能缓存
+        let timed_out: Vec<Box<dyn LyricsProviderTrait>> =
+            vec![Box::new(MockProvider { source: "broken", lyrics: None, synced: true, delta_abs: 0 }), Box::new(SlowProvider)];
+        let (result, all_not_found) = fetch_lyrics(&timed_out, "", "", "", 0, "", &[], FetchStrategy::Best, true, Duration::from_millis(50), &AtomicU64::default());
+        assert!(result.is_empty());
+        assert!(!all_not_found);
+    }
+
+    #[test]
+    fn test_spread_plain_lyrics() {
+        let text = "第一句\n\n第二句\n第三句\n第四句";
+        assert_eq!(
+            spread_plain_lyrics(text, 200000),
+            BTreeMap::from([
+                (0, String::from("第一句")),
+                (50000, String::from("第二句")),
+                (100000, String::from("第三句")),
+                (150000, String::from("第四句")),
+            ])
+        );
+        // 不知道时长时只显示第一行
+        assert_eq!(spread_plain_lyrics(text, 0), BTreeMap::from([(0, String::from("第一句"))]));
+    }
+
+    #[test]
+    fn test_sort_prefers_synced_lyrics() {
+        let sort_list = vec![String::from("netease"), String::from("qq")];
+        let plain = SearchLyricsInfo {
+            source: String::from("netease"),
+            lyrics: BTreeMap::from([(0, String::from("第一句\n第二句"))]),
+            translations: BTreeMap::new(),
+            synced: false,
+            delta_abs: 0,
+            title_similarity: 1.0,
+        };
+        let synced = SearchLyricsInfo {
+            source: String::from("qq"),
+            lyrics: BTreeMap::from([(0, String::from("第一句"))]),
+            translations: BTreeMap::new(),
+            synced: true,
+            delta_abs: 2000,
+            title_similarity: 1.0,
+        };
+
+        let mut list = vec![plain, synced];
+        sort_search_lyrics_info_list(&mut list, &sort_list, true);
+        assert_eq!(list[0].source, "qq");
+
+        // 关闭时仍然按时长差排序
+        sort_search_lyrics_info_list(&mut list, &sort_list, false);
+        assert_eq!(list[0].source, "netease");
+    }
+
+    #[test]
+    fn test_sort_scores_title_and_translation() {
+        let sort_list = vec![String::from("netease"), String::from("qq")];
+        let info = |source: &str, delta_abs: i64, title_similarity: f64, translated: bool| SearchLyricsInfo {
+            source: String::from(source),
+            lyrics: BTreeMap::from([(0, String::from("第一句"))]),
+            translations: if translated {
+                BTreeMap::from([(0, String::from("first line"))])
+            } else {
+                BTreeMap::new()
+            },
+            synced: true,
+            delta_abs,
+            title_similarity,
+        };
+
+        // 标题更接近的优先，即使时长差更大
+        let mut list = vec![info("netease", 0, 0.8, false), info("qq", 3000, 1.0, false)];
+        sort_search_lyrics_info_list(&mut list, &sort_list, true);
+        assert_eq!(list[0].source, "qq");
+
+        // 时长差在一秒以内时有翻译的优先
+        let mut list = vec![info("netease", 0, 1.0, false), info("qq", 500, 1.0, true)];
+        sort_search_lyrics_info_list(&mut list, &sort_list, true);
+        assert_eq!(list[0].source, "qq");
+        let mut list = vec![info("netease", 0, 1.0, false), info("qq", 1500, 1.0, true)];
+        sort_search_lyrics_info_list(&mut list, &sort_list, true);
+        assert_eq!(list[0].source, "netease");
     }
 }
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicU64;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::api::{LyricsProviderTrait, SearchLyricsInfo};
+
+/// 单首歌曲的歌词指定方式
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum Override {
+    // 本地 .lrc 文件，相对路径相对于 overrides.toml 所在目录
+    File { path: PathBuf },
+    // 指定歌词源中的歌曲 id
+    Song { source: String, song_id: SongId },
+}
+
+// 歌曲 id 写成数字或字符串都可以
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+pub enum SongId {
+    Number(u64),
+    Text(String),
+}
+
+impl fmt::Display for SongId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SongId::Number(id) => write!(f, "{}", id),
+            SongId::Text(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+/// 按曲目指定歌词，键为 "歌手 - 标题"，自动匹配总是出错的歌曲可以在这里固定歌词
+#[derive(Default)]
+pub struct Overrides {
+    dir: PathBuf,
+    entries: HashMap<String, Override>,
+}
+
+impl Overrides {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        Ok(Overrides {
+            dir: path.parent().map(Path::to_path_buf).unwrap_or_default(),
+            entries: toml::from_str(&fs::read_to_string(path)?)?,
+        })
+    }
+
+    fn get(&self, artist: &str, title: &str) -> Option<&Override> {
+        self.entries.get(&format!("{} - {}", artist, title))
+    }
+
+    // 按覆盖配置获取歌词，没有配置或获取失败时返回 None，继续走缓存和歌词源
+    pub fn resolve(
+        &self,
+        artist: &str,
+        title: &str,
+        providers: &[Box<dyn LyricsProviderTrait>],
+        timeout: Duration,
+        heartbeat: &AtomicU64,
+    ) -> Option<SearchLyricsInfo> {
+        match self.get(artist, title)? {
+            Override::File { path } => {
+                let path = self.dir.join(path);
+                let text = fs::read_to_string(&path)
+                    .map_err(|err| eprintln!("覆盖配置的歌词文件读取失败 {}: {}", path.display(), err))
+                    .ok()?;
+                let lyrics = SearchLyricsInfo::parse_lyric(&text)
+                    .map_err(|err| eprintln!("覆盖配置的歌词文件解析失败 {}: {}", path.display(), err))
+                    .ok()?;
+                Some(SearchLyricsInfo {
+                    source: String::from("override"),
+                    lyrics,
+                    translations: BTreeMap::new(),
+                    synced: true,
+                    delta_abs: 0,
+                    title_similarity: 1.0,
+                })
+            }
+            Override::Song { source, song_id } => {
+                let Some(provider) = providers.iter().find(|provider| provider.get_source_name() == *source) else {
+                    eprintln!("覆盖配置的歌词源 {} 不可用", source);
+                    return None;
+                };
+                if !provider.capabilities().id_lookup {
+                    eprintln!("歌词源 {} 不支持按 id 获取歌词", source);
+                    return None;
+                }
+                // 和自动匹配一样限制等待时间并捕获 panic
+                let runtime = tokio::runtime::Runtime::new().unwrap();
+                let song_id_text = song_id.to_string();
+                crate::call_provider(&runtime, timeout, heartbeat, provider.get_lyric_by_id(&song_id_text))
+                    .map_err(|err| eprintln!("[{}]源按 id {} 获取歌词失败: {}", source, song_id, err))
+                    .ok()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use crate::api::ProviderCapabilities;
+
+    fn test_overrides(name: &str, content: &str) -> (PathBuf, Overrides) {
+        let dir = std::env::temp_dir().join(format!("mpris-lyrics-rs-overrides-{}", name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("overrides.toml");
+        fs::write(&path, content).unwrap();
+        (dir, Overrides::load(&path).unwrap())
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let (_, overrides) = test_overrides(
+            "parse",
+            r#"
+            "五月天 - 玫瑰少年" = { source = "netease", song_id = 191895 }
+            "BY2 - 愛丫愛丫" = { source = "qq", song_id = "003QrvzS3248Wi" }
+            "Someone - Song" = { path = "song.lrc" }
+            "#,
+        );
+        assert_eq!(
+            overrides.get("五月天", "玫瑰少年"),
+            Some(&Override::Song { source: String::from("netease"), song_id: SongId::Number(191895) })
+        );
+        match overrides.get("BY2", "愛丫愛丫") {
+            Some(Override::Song { song_id, .. }) => assert_eq!(song_id.to_string(), "003QrvzS3248Wi"),
+            other => panic!("unexpected override: {:?}", other),
+        }
+        assert_eq!(
+            overrides.get("Someone", "Song"),
+            Some(&Override::File { path: PathBuf::from("song.lrc") })
+        );
+        assert_eq!(overrides.get("五月天", "知足"), None);
+    }
+
+    #[test]
+    fn test_resolve_file_override() {
+        let (dir, overrides) = test_overrides(
+            "file",
+            r#"
+            "Someone - Song" = { path = "song.lrc" }
+            "Someone - Missing" = { path = "missing.lrc" }
+            "Someone - Unknown" = { source = "unknown", song_id = 1 }
+            "#,
+        );
+        fs::write(dir.join("song.lrc"), "[00:01.00]第一句").unwrap();
+
+        let info = overrides.resolve("Someone", "Song", &[], Duration::from_secs(1), &AtomicU64::default()).unwrap();
+        assert_eq!(info.source, "override");
+        assert_eq!(info.lyrics.get(&1000).unwrap(), "第一句");
+        // 文件不存在或歌词源不可用时交给正常的流程
+        assert!(overrides.resolve("Someone", "Missing", &[], Duration::from_secs(1), &AtomicU64::default()).is_none());
+        assert!(overrides.resolve("Someone", "Unknown", &[], Duration::from_secs(1), &AtomicU64::default()).is_none());
+        assert!(overrides.resolve("Someone", "Other", &[], Duration::from_secs(1), &AtomicU64::default()).is_none());
+    }
+
+    struct PanickingProvider;
+
+    #[async_trait]
+    impl LyricsProviderTrait for PanickingProvider {
+        fn get_source_name(&self) -> String {
+            String::from("panicking")
+        }
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities {
+                id_lookup: true,
+                ..Default::default()
+            }
+        }
+        async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, _url: &str) -> anyhow::Result<SearchLyricsInfo> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+        async fn get_lyric_by_id(&self, _id: &str) -> anyhow::Result<SearchLyricsInfo> {
+            panic!("unexpected upstream json");
+        }
+    }
+
+    struct SlowProvider;
+
+    #[async_trait]
+    impl LyricsProviderTrait for SlowProvider {
+        fn get_source_name(&self) -> String {
+            String::from("slow")
+        }
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities {
+                id_lookup: true,
+                ..Default::default()
+            }
+        }
+        async fn get_best_match_lyric(&self, _title: &str, _artist: &str, _album: &str, _length: u64, _url: &str) -> anyhow::Result<SearchLyricsInfo> {
+            Err(anyhow::anyhow!("unreachable"))
+        }
+        async fn get_lyric_by_id(&self, _id: &str) -> anyhow::Result<SearchLyricsInfo> {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            Err(anyhow::anyhow!("unreachable"))
+        }
+    }
+
+    #[test]
+    fn test_resolve_song_override_failures() {
+        let (_, overrides) = test_overrides(
+            "song",
+            r#"
+            "Someone - Panic" = { source = "panicking", song_id = 1 }
+            "Someone - Slow" = { source = "slow", song_id = 1 }
+            "#,
+        );
+        let providers: Vec<Box<dyn LyricsProviderTrait>> = vec![Box::new(PanickingProvider), Box::new(SlowProvider)];
+        let heartbeat = AtomicU64::default();
+
+        // 歌词源 panic 或超时都按获取失败处理，交给正常的流程，并且请求前会更新心跳
+        assert!(overrides.resolve("Someone", "Panic", &providers, Duration::from_secs(1), &heartbeat).is_none());
+        assert_ne!(heartbeat.load(std::sync::atomic::Ordering::Relaxed), 0);
+        let start = std::time::Instant::now();
+        assert!(overrides.resolve("Someone", "Slow", &providers, Duration::from_millis(100), &heartbeat).is_none());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,2 @@
+pub mod string;
+pub mod translit;
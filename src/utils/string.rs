@@ -0,0 +1,61 @@
+// 没有启用网易云和 QQ 音乐歌词源时，只在测试中使用
+#![cfg_attr(not(any(feature = "netease", feature = "qqmusic")), allow(dead_code))]
+
+use regex::Regex;
+
+/// 去掉标题中影响搜索的附加信息，只用于生成搜索关键词
+///
+/// 例如 "Song (feat. X) - 2019 Remaster" 会变成 "Song"，
+/// 匹配搜索结果时和原标题一起计算相似度，取较高的一个
+pub fn normalize_search_title(title: &str) -> String {
+    // 括号中的 feat./ft./Remaster(ed)/Live，如 "(feat. X)"、"[Live]"、"（2011 Remastered）"
+    let bracket_regex = Regex::new(
+        r"(?i)\s*[(\[（【][^)\]）】]*?\b(?:feat\.?|ft\.?|remaster(?:ed)?|live)(?:\b|\.)[^)\]）】]*[)\]）】]",
+    )
+    .unwrap();
+    // 括号中的年份，如 "(2019)"
+    let year_regex = Regex::new(r"\s*[(\[（【]\s*\d{4}\s*[)\]）】]").unwrap();
+    // " - " 之后的 Remaster/Live 后缀，如 " - 2019 Remaster"、" - Live at Wembley"
+    let suffix_regex = Regex::new(r"(?i)\s+-\s+.*\b(?:remaster(?:ed)?|live)\b.*$").unwrap();
+    // 没有括号的 feat.，如 "Song feat. X"
+    let feat_regex = Regex::new(r"(?i)\s+(?:feat|ft)\.\s.*$").unwrap();
+
+    let mut normalized = title.to_string();
+    for regex in [&bracket_regex, &year_regex, &suffix_regex, &feat_regex] {
+        normalized = regex.replace_all(&normalized, "").into_owned();
+    }
+    let normalized = normalized.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    // 整个标题都被去掉时使用原标题
+    if normalized.is_empty() {
+        title.trim().to_string()
+    } else {
+        normalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_search_title() {
+        assert_eq!(normalize_search_title("Song (feat. X) - 2019 Remaster"), "Song");
+        assert_eq!(normalize_search_title("Bohemian Rhapsody - Remastered 2011"), "Bohemian Rhapsody");
+        assert_eq!(normalize_search_title("Yellow (Live at Glastonbury)"), "Yellow");
+        assert_eq!(normalize_search_title("Stay [ft. Justin Bieber]"), "Stay");
+        assert_eq!(normalize_search_title("Shape of You feat. Stormzy"), "Shape of You");
+        assert_eq!(normalize_search_title("Heroes (2017 Remaster)"), "Heroes");
+        assert_eq!(normalize_search_title("Wonderwall (1995)"), "Wonderwall");
+        assert_eq!(normalize_search_title("十年（Live）"), "十年");
+        assert_eq!(normalize_search_title("Hotel California - Live On MTV, 1994"), "Hotel California");
+    }
+
+    #[test]
+    fn test_normalize_search_title_keeps_plain_title() {
+        assert_eq!(normalize_search_title("玫瑰少年"), "玫瑰少年");
+        assert_eq!(normalize_search_title("Alive (Remix)"), "Alive (Remix)");
+        assert_eq!(normalize_search_title("Gift - Acoustic"), "Gift - Acoustic");
+        assert_eq!(normalize_search_title("  Live  "), "Live");
+    }
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+
+/// 在歌词下方显示的注音
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Transliteration {
+    #[default]
+    None,
+    // 汉字转为不带声调的拼音
+    Pinyin,
+    // 假名转为罗马音，日文汉字没有读音词典，保持原样
+    Romaji,
+}
+
+fn is_han(c: char) -> bool {
+    matches!(c, '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}')
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3041}'..='\u{309F}' | '\u{30A0}'..='\u{30FF}')
+}
+
+// 中日文标点，转为对应的 ASCII 标点
+fn is_cjk_punctuation(c: char) -> bool {
+    matches!(c, '\u{3000}'..='\u{303F}' | '\u{FF00}'..='\u{FFEF}')
+}
+
+/// 生成一行歌词的注音，没有需要转换的字符（如纯英文歌词）时返回 `None`
+pub fn transliterate(text: &str, mode: Transliteration) -> Option<String> {
+    let convert: fn(char) -> bool = match mode {
+        Transliteration::None => return None,
+        Transliteration::Pinyin => is_han,
+        Transliteration::Romaji => is_kana,
+    };
+    if !text.chars().any(convert) {
+        return None;
+    }
+
+    let mut result = String::new();
+    let mut chars = text.chars().peekable();
+    // 上一个字是否转换过，转换过的字和相邻的文字之间用空格隔开
+    let mut after_converted = false;
+    while let Some(c) = chars.next() {
+        if convert(c) {
+            if !result.is_empty() && !result.ends_with(char::is_whitespace) && !after_converted {
+                result.push(' ');
+            }
+            match mode {
+                // 拼音逐字用空格隔开
+                Transliteration::Pinyin => {
+                    if after_converted {
+                        result.push(' ');
+                    }
+                    result.push_str(&any_ascii::any_ascii_char(c).to_lowercase());
+                }
+                // 连续的假名合成一个词
+                _ => {
+                    let mut kana = String::from(c);
+                    while let Some(&next) = chars.peek().filter(|next| is_kana(**next)) {
+                        kana.push(next);
+                        chars.next();
+                    }
+                    result.push_str(&kana_to_romaji(&kana));
+                }
+            }
+            after_converted = true;
+        } else if is_cjk_punctuation(c) {
+            result.push_str(any_ascii::any_ascii_char(c));
+            after_converted = false;
+        } else {
+            if after_converted && !c.is_whitespace() {
+                result.push(' ');
+            }
+            result.push(c);
+            after_converted = false;
+        }
+    }
+    Some(result.trim().to_string())
+}
+
+// 逐个假名转换，再处理拗音（きょ）、促音（っ）和长音符（ー）
+fn kana_to_romaji(kana: &str) -> String {
+    let mut syllables: Vec<String> = Vec::new();
+    let mut double_next = false;
+    for c in kana.chars() {
+        match c {
+            'っ' | 'ッ' => double_next = true,
+            'ゃ' | 'ゅ' | 'ょ' | 'ャ' | 'ュ' | 'ョ' => {
+                let small = any_ascii::any_ascii_char(c);
+                match syllables.last_mut() {
+                    // き + ょ → kyo，し + ょ → sho
+                    Some(last) if last.len() > 1 && last.ends_with('i') => {
+                        last.pop();
+                        if last.ends_with("sh") || last.ends_with("ch") || last.ends_with('j') {
+                            last.push_str(&small[1..]);
+                        } else {
+                            last.push_str(small);
+                        }
+                    }
+                    _ => syllables.push(small.to_string()),
+                }
+            }
+            'ー' => {
+                if let Some(vowel) = syllables.last().and_then(|last| last.chars().last()) {
+                    syllables.push(vowel.to_string());
+                }
+            }
+            _ => {
+                let mut syllable = any_ascii::any_ascii_char(c).to_lowercase();
+                if std::mem::take(&mut double_next) {
+                    // っち → tchi，其他重复下一个辅音
+                    match syllable.chars().next() {
+                        Some('c') => syllable.insert(0, 't'),
+                        Some(first) if !"aiueon".contains(first) => syllable.insert(0, first),
+                        _ => {}
+                    }
+                }
+                syllables.push(syllable);
+            }
+        }
+    }
+    syllables.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transliterate_pinyin() {
+        assert_eq!(transliterate("北京欢迎你", Transliteration::Pinyin).unwrap(), "bei jing huan ying ni");
+        assert_eq!(transliterate("你好，世界", Transliteration::Pinyin).unwrap(), "ni hao, shi jie");
+        assert_eq!(transliterate("我爱你 love", Transliteration::Pinyin).unwrap(), "wo ai ni love");
+        assert_eq!(transliterate("我的love", Transliteration::Pinyin).unwrap(), "wo de love");
+        assert_eq!(transliterate("Hello world", Transliteration::Pinyin), None);
+        assert_eq!(transliterate("北京", Transliteration::None), None);
+    }
+
+    #[test]
+    fn test_transliterate_romaji() {
+        assert_eq!(transliterate("きょうは", Transliteration::Romaji).unwrap(), "kyouha");
+        assert_eq!(transliterate("ちゃんと", Transliteration::Romaji).unwrap(), "chanto");
+        assert_eq!(transliterate("がっこう", Transliteration::Romaji).unwrap(), "gakkou");
+        assert_eq!(transliterate("マッチ", Transliteration::Romaji).unwrap(), "matchi");
+        assert_eq!(transliterate("ラーメン", Transliteration::Romaji).unwrap(), "raamen");
+        // 日文汉字保持原样，和假名之间用空格隔开
+        assert_eq!(transliterate("いい天気", Transliteration::Romaji).unwrap(), "ii 天気");
+        assert_eq!(transliterate("天気", Transliteration::Romaji), None);
+    }
+}
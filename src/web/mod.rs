@@ -0,0 +1,86 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+use crate::NowPlaying;
+
+// 歌词页面，可直接作为 OBS 浏览器源使用
+const INDEX_HTML: &str = include_str!("index.html");
+
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(default)]
+pub struct WebConfig {
+    pub enabled: bool,
+    pub addr: String,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            enabled: false,
+            addr: String::from("127.0.0.1:8765"),
+        }
+    }
+}
+
+pub async fn serve(addr: &str, now_playing: watch::Receiver<NowPlaying>) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let now_playing = now_playing.clone();
+        tokio::spawn(async move {
+            // 客户端断开属于正常情况，不打印错误
+            let _ = handle_connection(stream, now_playing).await;
+        });
+    }
+}
+
+async fn handle_connection(
+    stream: TcpStream,
+    mut now_playing: watch::Receiver<NowPlaying>,
+) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // 读完剩余的请求头，内容用不到
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/");
+    match path {
+        "/" => {
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                INDEX_HTML.len(),
+                INDEX_HTML
+            );
+            writer.write_all(response.as_bytes()).await?;
+        }
+        "/events" => {
+            writer
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n")
+                .await?;
+            // 先推送一次当前状态，之后每次变化时推送
+            loop {
+                let data = serde_json::to_string(&*now_playing.borrow_and_update())?;
+                writer.write_all(format!("data: {}\n\n", data).as_bytes()).await?;
+                writer.flush().await?;
+                now_playing.changed().await?;
+            }
+        }
+        _ => {
+            writer
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .await?;
+        }
+    }
+    Ok(())
+}